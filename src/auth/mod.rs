@@ -0,0 +1,243 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::from_utf8,
+};
+
+use octocrab::Octocrab;
+
+use crate::config::SavedConfig;
+
+// Scopes a classic token needs for everything gg does. Fine-grained tokens don't report
+// scopes, so they can only be checked by using them.
+const REQUIRED_SCOPES: &[&str] = &["repo", "read:org"];
+
+// Where a token was found, in the order they're tried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Env,
+    GgCredentials,
+    GhHosts,
+    GitCredential,
+}
+
+impl Source {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Source::Env => "token env var",
+            Source::GgCredentials => "gg credentials file",
+            Source::GhHosts => "gh hosts.yml",
+            Source::GitCredential => "git credential helper",
+        }
+    }
+}
+
+// `env_var` is the forge's token variable, GITHUB_TOKEN for github. Only a broken gg
+// credentials file is an error, not finding a token anywhere is None.
+pub fn find_token(host: &str, env_var: &str) -> anyhow::Result<Option<(String, Source)>> {
+    if let Ok(token) = std::env::var(env_var) {
+        if !token.is_empty() {
+            return Ok(Some((token, Source::Env)));
+        }
+    }
+    if let Some(token) = read_credentials()?.remove(host) {
+        return Ok(Some((token, Source::GgCredentials)));
+    }
+    if let Some(token) = gh_hosts_token(host) {
+        return Ok(Some((token, Source::GhHosts)));
+    }
+    Ok(git_credential_token(host).map(|token| (token, Source::GitCredential)))
+}
+
+pub fn get_token(host: &str, env_var: &str) -> anyhow::Result<String> {
+    match find_token(host, env_var)? {
+        Some((token, _)) => Ok(token),
+        None => Err(anyhow::anyhow!(
+            "no token found for {}, run `gg auth login`",
+            host
+        )),
+    }
+}
+
+fn credentials_path() -> PathBuf {
+    Path::new(&dirs::home_dir().unwrap())
+        .join(".gg")
+        .join("credentials")
+}
+
+// Tokens keyed by host. The file is ignored if anyone but the owner can read it.
+fn read_credentials() -> anyhow::Result<HashMap<String, String>> {
+    let path = credentials_path();
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return Ok(HashMap::new()),
+    };
+    let mode = file
+        .metadata()
+        .expect("reading credentials")
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        println!(
+            "Ignoring {}, its permissions are {:o} but should be 600",
+            path.display(),
+            mode & 0o777
+        );
+        return Ok(HashMap::new());
+    }
+    let mut buf = String::new();
+    file.read_to_string(&mut buf).expect("reading credentials");
+    serde_json::from_str(buf.as_str()).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is not valid json ({}), fix or delete it",
+            path.display(),
+            e
+        )
+    })
+}
+
+fn write_credentials(creds: &HashMap<String, String>) {
+    let path = credentials_path();
+    fs::create_dir_all(path.parent().unwrap()).expect("creating ~/.gg");
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .expect("opening credentials file");
+    // mode only applies when the file is created.
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).expect("chmod credentials");
+    file.write_all(serde_json::to_string_pretty(creds).unwrap().as_bytes())
+        .expect("writing credentials");
+}
+
+pub fn save_token(host: &str, token: String) -> anyhow::Result<()> {
+    let mut creds = read_credentials()?;
+    creds.insert(host.to_string(), token);
+    write_credentials(&creds);
+    Ok(())
+}
+
+// Returns whether there was a token to remove.
+pub fn remove_token(host: &str) -> anyhow::Result<bool> {
+    let mut creds = read_credentials()?;
+    let removed = creds.remove(host).is_some();
+    if removed {
+        write_credentials(&creds);
+    }
+    Ok(removed)
+}
+
+fn gh_hosts_path() -> PathBuf {
+    match std::env::var("GH_CONFIG_DIR") {
+        Ok(dir) => Path::new(&dir).join("hosts.yml"),
+        Err(_) => Path::new(&dirs::home_dir().unwrap())
+            .join(".config")
+            .join("gh")
+            .join("hosts.yml"),
+    }
+}
+
+// hosts.yml is a map of host to settings, so rather than pulling in a yaml parser this finds
+// the host's top level key and the `oauth_token` indented under it.
+fn gh_hosts_token(host: &str) -> Option<String> {
+    let contents = fs::read_to_string(gh_hosts_path()).ok()?;
+    let mut in_host = false;
+    for line in contents.lines() {
+        if !line.starts_with(' ') && !line.starts_with('\t') {
+            in_host = line.trim_end().trim_end_matches(':').trim_matches('"') == host;
+            continue;
+        }
+        if !in_host {
+            continue;
+        }
+        if let Some(token) = line.trim().strip_prefix("oauth_token:") {
+            let token = token.trim().trim_matches('"').trim_matches('\'');
+            if !token.is_empty() {
+                return Some(token.to_string());
+            }
+        }
+    }
+    None
+}
+
+fn git_credential_token(host: &str) -> Option<String> {
+    let mut child = Command::new("git")
+        .arg("credential")
+        .arg("fill")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(format!("protocol=https\nhost={}\n\n", host).as_bytes())
+        .ok()?;
+    let out = child.wait_with_output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    from_utf8(&out.stdout)
+        .ok()?
+        .lines()
+        .find_map(|l| l.strip_prefix("password="))
+        .map(|p| p.to_string())
+}
+
+pub struct TokenInfo {
+    pub login: String,
+    // None for tokens that don't report scopes (fine-grained tokens).
+    pub scopes: Option<Vec<String>>,
+}
+
+impl TokenInfo {
+    pub fn missing_scopes(&self) -> Vec<&'static str> {
+        match &self.scopes {
+            Some(scopes) => REQUIRED_SCOPES
+                .iter()
+                .filter(|s| !scopes.iter().any(|have| have == *s))
+                .copied()
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+pub async fn validate_token(saved: &SavedConfig, token: String) -> anyhow::Result<TokenInfo> {
+    let octo = Octocrab::builder()
+        .personal_token(token)
+        .base_uri(saved.api_url()?)
+        .map_err(anyhow::Error::msg)?
+        .build()
+        .map_err(anyhow::Error::msg)?;
+    let res = octo._get("/user").await.map_err(anyhow::Error::msg)?;
+    let scopes = res
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            v.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        });
+    let body = octo.body_to_string(res).await.map_err(anyhow::Error::msg)?;
+    let user: serde_json::Value =
+        serde_json::from_str(body.as_str()).map_err(anyhow::Error::msg)?;
+    // Gitlab calls it the username.
+    let login = user["login"]
+        .as_str()
+        .or_else(|| user["username"].as_str())
+        .ok_or_else(|| anyhow::anyhow!("unexpected /user response"))?
+        .to_string();
+    Ok(TokenInfo { login, scopes })
+}
@@ -0,0 +1,153 @@
+use std::{fs, path::Path};
+
+use crate::config::get_repo_root_path;
+
+// Where github and gitlab look for the file, first one found wins.
+const LOCATIONS: &[&str] = &[
+    ".github/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+];
+
+struct Rule {
+    pattern: String,
+    owners: Vec<String>,
+}
+
+// The owners of the given files, as logins or `org/team`s. Like github, the last rule that
+// matches a file decides its owners. Owners given as email addresses are left out since
+// there's no login to request a review from.
+pub(crate) fn owners_of(files: &[String]) -> Vec<String> {
+    let rules = load_rules();
+    let mut owners: Vec<String> = vec![];
+    for file in files {
+        let rule = rules
+            .iter()
+            .rev()
+            .find(|r| pattern_matches(&r.pattern, file));
+        for owner in rule.map(|r| r.owners.iter()).into_iter().flatten() {
+            if !owners.contains(owner) {
+                owners.push(owner.clone());
+            }
+        }
+    }
+    owners
+}
+
+fn load_rules() -> Vec<Rule> {
+    let root = get_repo_root_path();
+    let contents = LOCATIONS
+        .iter()
+        .find_map(|loc| fs::read_to_string(Path::new(root.as_str()).join(loc)).ok())
+        .unwrap_or_default();
+    contents
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or("").trim())
+        // Gitlab's [Section] headers group rules, the rules themselves read the same.
+        .filter(|l| !l.is_empty() && !l.starts_with('[') && !l.starts_with('^'))
+        .filter_map(|l| {
+            let mut fields = l.split_whitespace();
+            let pattern = fields.next()?.to_string();
+            let owners = fields
+                .filter_map(|o| o.strip_prefix('@'))
+                .map(|o| o.to_string())
+                .collect();
+            Some(Rule { pattern, owners })
+        })
+        .collect()
+}
+
+// Gitignore style: a pattern with a slash (other than a trailing one) is anchored at the
+// root, one without matches at any depth, and a directory matches everything under it. A
+// glob in the last segment only matches at that level, so `docs/*` is just docs' own files.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let anchored = pattern.starts_with('/') || pattern.trim_end_matches('/').contains('/');
+    let mut pattern = pattern.trim_start_matches('/').to_string();
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    if !anchored {
+        pattern = format!("**/{}", pattern);
+    }
+    let last = pattern.rsplit('/').next().unwrap_or("");
+    let may_be_dir = !last.contains('*') && !last.contains('?');
+    glob_matches(pattern.as_bytes(), path.as_bytes())
+        || (may_be_dir && glob_matches(format!("{}/**", pattern).as_bytes(), path.as_bytes()))
+}
+
+// `*` and `?` stay within a path segment, `**` crosses them and `**/` can match nothing.
+fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+    if let Some(rest) = pattern.strip_prefix(b"**/") {
+        return glob_matches(rest, path)
+            || path
+                .iter()
+                .enumerate()
+                .any(|(i, c)| *c == b'/' && glob_matches(rest, &path[i + 1..]));
+    }
+    if let Some(rest) = pattern.strip_prefix(b"**") {
+        return (0..=path.len()).any(|i| glob_matches(rest, &path[i..]));
+    }
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(b'*') => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_matches(&pattern[1..], &path[i..])),
+        Some(b'?') => match path.first() {
+            Some(c) if *c != b'/' => glob_matches(&pattern[1..], &path[1..]),
+            _ => false,
+        },
+        Some(c) => path.first() == Some(c) && glob_matches(&pattern[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pattern_matches;
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        assert!(pattern_matches("*.js", "app.js"));
+        assert!(pattern_matches("*.js", "src/ui/app.js"));
+        assert!(!pattern_matches("*.js", "src/app.jsx"));
+        assert!(pattern_matches("README.md", "docs/README.md"));
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored() {
+        assert!(pattern_matches("/build.rs", "build.rs"));
+        assert!(!pattern_matches("/build.rs", "src/build.rs"));
+        assert!(pattern_matches("src/main.rs", "src/main.rs"));
+        assert!(!pattern_matches("src/main.rs", "crates/src/main.rs"));
+    }
+
+    #[test]
+    fn directories_match_everything_under_them() {
+        assert!(pattern_matches("docs/", "docs/a/b.md"));
+        assert!(pattern_matches("/docs", "docs/a/b.md"));
+        assert!(pattern_matches("apps", "web/apps/index.ts"));
+        assert!(!pattern_matches("docs/", "src/docs.rs"));
+    }
+
+    #[test]
+    fn trailing_glob_only_matches_direct_children() {
+        assert!(pattern_matches("docs/*", "docs/a.md"));
+        assert!(!pattern_matches("docs/*", "docs/a/b.md"));
+        assert!(!pattern_matches("docs/*.md", "docs/a/b.md"));
+    }
+
+    #[test]
+    fn double_star_crosses_directories() {
+        assert!(pattern_matches("docs/**/*.md", "docs/a.md"));
+        assert!(pattern_matches("docs/**/*.md", "docs/a/b/c.md"));
+        assert!(pattern_matches("**/logs", "deep/down/logs/today.txt"));
+        assert!(!pattern_matches("docs/**/*.md", "docs/a/b.rs"));
+    }
+
+    #[test]
+    fn single_char_glob_stays_in_a_segment() {
+        assert!(pattern_matches("src/?.rs", "src/a.rs"));
+        assert!(!pattern_matches("src/?.rs", "src/ab.rs"));
+        assert!(!pattern_matches("a?b", "a/b"));
+    }
+}
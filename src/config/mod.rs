@@ -1,4 +1,6 @@
+use crate::{auth, color};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
@@ -9,7 +11,8 @@ use std::str::from_utf8;
 pub struct FullConfig {
     pub saved: SavedConfig,
     pub repo_name: String,
-    pub github_token: String,
+    // The api token for the repo's forge, whichever one that is.
+    pub token: String,
     pub status_file: PathBuf,
     pub status_file_backup_dir: PathBuf,
 }
@@ -23,21 +26,243 @@ pub struct SavedConfig {
 
     #[serde(default = "default_split")]
     pub branch_split: String,
+
+    // For Github Enterprise Server, the host of the instance. The API urls are derived from
+    // it unless they're set explicitly.
+    #[serde(default = "default_github_host")]
+    pub github_host: String,
+    #[serde(default)]
+    pub github_api_url: Option<String>,
+    #[serde(default)]
+    pub github_graphql_url: Option<String>,
+
+    // Where the repo is hosted: github, gitlab or gitea (which covers forgejo too). Forges
+    // other than github get their host (and api url, if it's not in the usual place) from
+    // forge_host and forge_api_url.
+    #[serde(default = "default_forge")]
+    pub forge: String,
+    #[serde(default)]
+    pub forge_host: Option<String>,
+    #[serde(default)]
+    pub forge_api_url: Option<String>,
+
+    // How long cached PRs and issues (in ~/.gg/db.sqlite) are used before asking the forge again.
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: i64,
+
+    // Extra commands to run for each gg hook (see the hooks module), keyed by hook name.
+    #[serde(default)]
+    pub hooks: HashMap<String, Vec<String>>,
+
+    // Stacks whose first part builds on another stack's part, keyed by stack base.
+    #[serde(default)]
+    pub stack_deps: HashMap<String, String>,
+
+    // Added to every PR gg opens, on top of CODEOWNERS reviewers and the linked issue's labels.
+    #[serde(default)]
+    pub default_labels: Vec<String>,
+    #[serde(default)]
+    pub default_assignees: Vec<String>,
+
+    // Configs from before the checks existed get them all off, so upgrading doesn't make
+    // 'gg land' refuse PRs it used to merge. New configs start with them on.
+    #[serde(default = "LandChecks::off")]
+    pub land_checks: LandChecks,
+
+    // How `gg land` merges: MERGE, SQUASH or REBASE. The templates make the merge commit's
+    // title and message out of {title}, {number}, {body} and {resolves} (`Resolves #N` for
+    // the linked issue, or nothing), the forge's defaults are used without them.
+    #[serde(default = "default_merge_method")]
+    pub merge_method: String,
+    #[serde(default)]
+    pub merge_title_template: Option<String>,
+    #[serde(default)]
+    pub merge_message_template: Option<String>,
+}
+
+// What `gg land` requires of a PR before merging it (unless it's run with --force). Within a
+// land_checks section, each one is on until turned off.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LandChecks {
+    #[serde(default = "default_true")]
+    pub approved: bool,
+    #[serde(default = "default_true")]
+    pub checks_green: bool,
+    #[serde(default = "default_true")]
+    pub mergeable: bool,
+    #[serde(default = "default_true")]
+    pub threads_resolved: bool,
+    // No fixup!, squash! or WIP commits in the branch.
+    #[serde(default = "default_true")]
+    pub no_wip_commits: bool,
+}
+
+impl Default for LandChecks {
+    fn default() -> LandChecks {
+        LandChecks {
+            approved: true,
+            checks_green: true,
+            mergeable: true,
+            threads_resolved: true,
+            no_wip_commits: true,
+        }
+    }
+}
+
+impl LandChecks {
+    fn off() -> LandChecks {
+        LandChecks {
+            approved: false,
+            checks_green: false,
+            mergeable: false,
+            threads_resolved: false,
+            no_wip_commits: false,
+        }
+    }
+}
+
+fn default_merge_method() -> String {
+    "SQUASH".to_string()
+}
+
+fn default_true() -> bool {
+    true
 }
 
 fn default_split() -> String {
     "/".to_string()
 }
 
+const GITHUB_COM: &str = "github.com";
+const GITLAB_COM: &str = "gitlab.com";
+
+pub const GITHUB: &str = "github";
+pub const GITLAB: &str = "gitlab";
+pub const GITEA: &str = "gitea";
+
+fn default_github_host() -> String {
+    GITHUB_COM.to_string()
+}
+
+fn default_cache_ttl_secs() -> i64 {
+    60
+}
+
+fn default_forge() -> String {
+    GITHUB.to_string()
+}
+
+impl SavedConfig {
+    // The host of whichever forge the repo is on.
+    pub fn host(&self) -> anyhow::Result<String> {
+        match (self.forge.as_str(), &self.forge_host) {
+            (GITHUB, _) => Ok(self.github_host.clone()),
+            (GITLAB, Some(host)) if !host.is_empty() => Ok(host.clone()),
+            (GITLAB, _) => Ok(GITLAB_COM.to_string()),
+            (GITEA, Some(host)) if !host.is_empty() => Ok(host.clone()),
+            (GITEA, _) => Err(anyhow::anyhow!(
+                "forge_host is required for gitea, set it in {}",
+                get_saved_config_file_path().display()
+            )),
+            (forge, _) => Err(anyhow::anyhow!(
+                "unknown forge {} in {}, expected {}, {} or {}",
+                forge,
+                get_saved_config_file_path().display(),
+                GITHUB,
+                GITLAB,
+                GITEA
+            )),
+        }
+    }
+
+    pub fn token_env_var(&self) -> &'static str {
+        match self.forge.as_str() {
+            GITLAB => "GITLAB_TOKEN",
+            GITEA => "GITEA_TOKEN",
+            _ => "GITHUB_TOKEN",
+        }
+    }
+
+    pub fn api_url(&self) -> anyhow::Result<String> {
+        match self.forge.as_str() {
+            GITLAB => self.gitlab_api_url(),
+            GITEA => match &self.forge_api_url {
+                Some(url) => Ok(url.clone()),
+                None => Ok(format!("https://{}/api/v1", self.host()?)),
+            },
+            _ => Ok(self.github_api_url()),
+        }
+    }
+
+    // Where PRs and issues are browsed, for the forges other than github. An explicit api url
+    // (a local http instance say) is trusted for the scheme and port.
+    pub fn forge_web_url(&self) -> anyhow::Result<String> {
+        match &self.forge_api_url {
+            Some(url) => Ok(url
+                .trim_end_matches('/')
+                .trim_end_matches("/api/v1")
+                .trim_end_matches("/api/v4")
+                .to_string()),
+            None => Ok(format!("https://{}", self.host()?)),
+        }
+    }
+
+    pub fn gitlab_api_url(&self) -> anyhow::Result<String> {
+        match &self.forge_api_url {
+            Some(url) => Ok(url.clone()),
+            None => Ok(format!("https://{}/api/v4", self.host()?)),
+        }
+    }
+
+    // Next to the rest api, so a self-hosted instance's scheme and port carry over.
+    pub fn gitlab_graphql_url(&self) -> anyhow::Result<String> {
+        Ok(format!("{}/api/graphql", self.forge_web_url()?))
+    }
+
+    pub fn github_web_url(&self) -> String {
+        format!("https://{}", self.github_host)
+    }
+
+    pub fn github_api_url(&self) -> String {
+        match &self.github_api_url {
+            Some(url) => url.clone(),
+            None if self.github_host == GITHUB_COM => "https://api.github.com".to_string(),
+            None => format!("https://{}/api/v3", self.github_host),
+        }
+    }
+
+    pub fn github_graphql_url(&self) -> String {
+        match &self.github_graphql_url {
+            Some(url) => url.clone(),
+            None if self.github_host == GITHUB_COM => "https://api.github.com/graphql".to_string(),
+            None => format!("https://{}/api/graphql", self.github_host),
+        }
+    }
+}
+
+// Without a token there's nothing to do but say how to get one, so this exits rather than
+// leaving every command to handle it.
 pub fn get_full_config() -> FullConfig {
+    match try_get_full_config() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}", color::red(e.to_string()));
+            std::process::exit(1);
+        }
+    }
+}
+
+pub fn try_get_full_config() -> anyhow::Result<FullConfig> {
     let homedir = dirs::home_dir().unwrap();
-    FullConfig {
-        saved: get_saved_config(),
+    let saved = get_saved_config();
+    let token = auth::get_token(&saved.host()?, saved.token_env_var())?;
+    Ok(FullConfig {
+        saved,
         repo_name: get_repo_name(),
-        github_token: std::env::var("GITHUB_TOKEN").expect("GITHUB_TOKEN env var is required"),
+        token,
         status_file: homedir.join("status.txt"),
         status_file_backup_dir: homedir.join("status_bu"),
-    }
+    })
 }
 
 fn get_repo_name() -> String {
@@ -51,7 +276,7 @@ fn get_repo_name() -> String {
     };
     let x: &[_] = &[' ', '\t', '\n', '\r'];
     let result = from_utf8(&out.stdout).expect("msg").trim_end_matches(x);
-    result.split('/').last().unwrap().to_string()
+    result.split('/').next_back().unwrap().to_string()
 }
 
 pub fn get_saved_config() -> SavedConfig {
@@ -75,7 +300,7 @@ fn get_saved_config_file_path() -> PathBuf {
         .join("GG_CONFIG")
 }
 
-fn get_repo_root_path() -> String {
+pub fn get_repo_root_path() -> String {
     let out = match Command::new("git")
         .arg("rev-parse")
         .arg("--show-toplevel")
@@ -110,6 +335,17 @@ pub fn get_selected_issue_number() -> u64 {
     cfg.linked_issue.unwrap_or(0)
 }
 
+pub fn update_stack_dependency(base: &str, dependency: Option<String>) {
+    let mut cfg = get_saved_config();
+
+    match dependency {
+        Some(dep) => cfg.stack_deps.insert(base.to_string(), dep),
+        None => cfg.stack_deps.remove(base),
+    };
+
+    write_saved_config(cfg);
+}
+
 pub fn update_prefix_and_split(prefix: &str, split: &str) {
     let mut cfg = get_saved_config();
 
@@ -150,17 +386,77 @@ fn create_saved_config() -> File {
     std::io::stdin().read_line(&mut split).unwrap();
     let split = split.trim_end_matches(x);
 
+    let forge = ask_forge();
+    let default_host = match forge {
+        GITLAB => GITLAB_COM,
+        GITEA => "",
+        _ => GITHUB_COM,
+    };
+    let host = ask_host(default_host);
+    let (github_host, forge_host) = match forge {
+        GITHUB => (host, None),
+        _ => (GITHUB_COM.to_string(), Some(host)),
+    };
+
     let config = SavedConfig {
         repo_main_branch: line.to_string(),
         linked_issue: None,
         branch_prefix: prefix.to_string(), // replace
         repo_org: org.to_string(),
         branch_split: split.to_string(),
+        github_host,
+        github_api_url: None,
+        github_graphql_url: None,
+        forge: forge.to_string(),
+        forge_host,
+        forge_api_url: None,
+        cache_ttl_secs: default_cache_ttl_secs(),
+        hooks: HashMap::new(),
+        stack_deps: HashMap::new(),
+        default_labels: vec![],
+        default_assignees: vec![],
+        land_checks: LandChecks::default(),
+        merge_method: default_merge_method(),
+        merge_title_template: None,
+        merge_message_template: None,
     };
 
     write_saved_config(config)
 }
 
+// Asks again until it's one of the forges gg knows, rather than quietly treating a typo as
+// github.
+fn ask_forge() -> &'static str {
+    loop {
+        println!(
+            "Where is the repo hosted, {}, {} or {} [{}]: ",
+            GITHUB, GITLAB, GITEA, GITHUB
+        );
+        let mut forge = String::new();
+        std::io::stdin().read_line(&mut forge).unwrap();
+        match forge.trim() {
+            "" | GITHUB => return GITHUB,
+            GITLAB => return GITLAB,
+            GITEA => return GITEA,
+            other => println!("{}", color::red(format!("gg doesn't know {}", other))),
+        }
+    }
+}
+
+// Gitea has no public instance to default to, so there the host has to be given.
+fn ask_host(default_host: &str) -> String {
+    loop {
+        println!("What is the host [{}]: ", default_host);
+        let mut host = String::new();
+        std::io::stdin().read_line(&mut host).unwrap();
+        match host.trim() {
+            "" if default_host.is_empty() => println!("{}", color::red("A host is needed")),
+            "" => return default_host.to_string(),
+            host => return host.to_string(),
+        }
+    }
+}
+
 fn write_saved_config(cfg: SavedConfig) -> File {
     let mut file = OpenOptions::new()
         .read(true)
@@ -179,3 +475,56 @@ fn write_saved_config(cfg: SavedConfig) -> File {
 
     file
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SavedConfig;
+
+    fn saved(forge: &str, forge_host: Option<&str>) -> SavedConfig {
+        serde_json::from_value(serde_json::json!({
+            "repo_main_branch": "main",
+            "linked_issue": null,
+            "branch_prefix": "me",
+            "repo_org": "org",
+            "forge": forge,
+            "forge_host": forge_host,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn defaults_the_host_per_forge() {
+        assert_eq!(saved("github", None).host().unwrap(), "github.com");
+        assert_eq!(saved("gitlab", None).host().unwrap(), "gitlab.com");
+        assert_eq!(
+            saved("gitea", Some("git.example.com")).api_url().unwrap(),
+            "https://git.example.com/api/v1"
+        );
+    }
+
+    #[test]
+    fn refuses_gitea_without_a_host() {
+        assert!(saved("gitea", None).host().is_err());
+        assert!(saved("gitea", Some("")).api_url().is_err());
+    }
+
+    #[test]
+    fn refuses_an_unknown_forge() {
+        assert!(saved("bitbucket", None).host().is_err());
+    }
+
+    #[test]
+    fn land_checks_are_off_for_configs_from_before_them() {
+        let checks = saved("github", None).land_checks;
+        assert!(!checks.approved && !checks.checks_green && !checks.no_wip_commits);
+    }
+
+    #[test]
+    fn land_checks_default_on_once_configured() {
+        let mut cfg = serde_json::to_value(saved("github", None)).unwrap();
+        cfg["land_checks"] = serde_json::json!({ "approved": false });
+        let checks = serde_json::from_value::<SavedConfig>(cfg).unwrap().land_checks;
+        assert!(!checks.approved);
+        assert!(checks.checks_green && checks.mergeable && checks.threads_resolved);
+    }
+}
@@ -1,17 +1,30 @@
-use std::{fs, path::{Path, PathBuf}};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+};
 
-use dirs::home_dir;
-use rusqlite::Connection;
-use tui::widgets::LineGauge;
+use rusqlite::{params, Connection, OptionalExtension};
 
+use crate::forge::{Issue, Pr};
 
-pub fn init_connection() -> Result<Connection> {
-    fs::create_dir_all(get_gg_dir_path())?;
-    let conn = Connection::open(get_db_file_path())?;
-    Workspace::init(conn);
-    Repo::init(conn);
-    LinkedIssue::init(conn);
-    Ok(conn)
+// Set by --offline, everything is served from the cache however old it is.
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
 }
 
 fn get_db_file_path() -> PathBuf {
@@ -22,61 +35,186 @@ fn get_gg_dir_path() -> PathBuf {
     Path::new(&dirs::home_dir().unwrap()).join(".gg")
 }
 
-struct Workspace {
-    github_user: String,
-    github_token: String,
+fn init_connection() -> Connection {
+    fs::create_dir_all(get_gg_dir_path()).expect("creating ~/.gg");
+    let conn = Connection::open(get_db_file_path()).expect("opening ~/.gg/db.sqlite");
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS login (
+            host       TEXT PRIMARY KEY,
+            login      TEXT NOT NULL,
+            fetched_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS pr (
+            repo       TEXT NOT NULL,
+            branch     TEXT NOT NULL,
+            pr         TEXT,
+            fetched_at INTEGER NOT NULL,
+            PRIMARY KEY (repo, branch)
+        );
+        CREATE TABLE IF NOT EXISTS issue (
+            repo       TEXT NOT NULL,
+            number     INTEGER NOT NULL,
+            issue      TEXT NOT NULL,
+            fetched_at INTEGER NOT NULL,
+            PRIMARY KEY (repo, number)
+        );
+        CREATE TABLE IF NOT EXISTS issue_list (
+            repo       TEXT PRIMARY KEY,
+            issues     TEXT NOT NULL,
+            fetched_at INTEGER NOT NULL
+        );",
+    )
+    .expect("creating cache tables");
+    conn
 }
 
-impl Workspace {
-    fn init(conn: Connection) -> Result<()> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS workspace (
-                github_user  TEXT NOT NULL,
-                github_token TEXT NOT NULL
-            )",
-            [],
-        )?;
+// `max_age` is in seconds, None takes an entry however old it is.
+fn oldest(max_age: Option<i64>) -> i64 {
+    match max_age {
+        Some(age) => now() - age,
+        None => i64::MIN,
     }
 }
 
-struct Repo {
-    local_path: String,
-    repo_org: String,
-    repo_name: String,
-    main_branch: String,
-    branch_prefix: String,
+// The cache, opened (and its tables made) once and shared by every lookup through it.
+pub struct Db {
+    conn: Mutex<Connection>,
 }
 
-impl Repo {
-    fn init(conn: Connection) -> Result<()> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS repo (
-                local_path TEXT PRIMARY KEY,
-                org TEXT NOT NULL,
-                name TEXT NOT NULL,
-                main_branch TEXT NOT NULL,
-                branch_prefix TEXT NOT NULL,
-            )",
-            [],
-        )?;
+impl Db {
+    pub fn open() -> Db {
+        Db {
+            conn: Mutex::new(init_connection()),
+        }
     }
-}
 
-struct LinkedIssue {
-    repo_name: String,
-    issue: u32,
-    branch: String,
-}
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    pub fn get_login(&self, host: &str, max_age: Option<i64>) -> Option<String> {
+        self.conn()
+            .query_row(
+                "SELECT login FROM login WHERE host = ?1 AND fetched_at >= ?2",
+                params![host, oldest(max_age)],
+                |row| row.get(0),
+            )
+            .optional()
+            .expect("reading cached login")
+    }
+
+    pub fn save_login(&self, host: &str, login: &str) {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO login (host, login, fetched_at) VALUES (?1, ?2, ?3)",
+                params![host, login, now()],
+            )
+            .expect("caching login");
+    }
+
+    // The cached PR (or lack of one) for each branch that has a recent enough entry.
+    pub fn get_prs(
+        &self,
+        repo: &str,
+        branches: &HashSet<String>,
+        max_age: Option<i64>,
+    ) -> HashMap<String, Option<Pr>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT branch, pr FROM pr WHERE repo = ?1 AND fetched_at >= ?2")
+            .unwrap();
+        let rows = stmt
+            .query_map(params![repo, oldest(max_age)], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .expect("reading cached PRs");
+        let mut prs = HashMap::new();
+        for row in rows {
+            let (branch, pr) = row.expect("reading cached PR");
+            if !branches.contains(&branch) {
+                continue;
+            }
+            // A row written by an older gg may not fit today's Pr, that's just a miss.
+            let pr = match pr.map(|p| serde_json::from_str::<Pr>(p.as_str())) {
+                Some(Ok(pr)) => Some(pr),
+                Some(Err(_)) => continue,
+                None => None,
+            };
+            prs.insert(branch, pr);
+        }
+        prs
+    }
 
-impl LinkedIssue {
-    fn init(conn: Connection) -> Result<()> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS linked_issue (
-                repo_name TEXT NOT NULL,
-                issue INTEGER,
-                branch TEXT NOT NULL,
-            )",
-            [],
-        )?;
+    pub fn save_prs(&self, repo: &str, prs: &HashMap<String, Option<Pr>>) {
+        let mut conn = self.conn();
+        let tx = conn.transaction().unwrap();
+        for (branch, pr) in prs {
+            let pr = pr.as_ref().map(|p| serde_json::to_string(p).unwrap());
+            tx.execute(
+                "INSERT OR REPLACE INTO pr (repo, branch, pr, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+                params![repo, branch, pr, now()],
+            )
+            .expect("caching PR");
+        }
+        tx.commit().expect("caching PRs");
     }
-}
\ No newline at end of file
+
+    pub fn forget_pr(&self, repo: &str, branch: &str) {
+        self.conn()
+            .execute(
+                "DELETE FROM pr WHERE repo = ?1 AND branch = ?2",
+                params![repo, branch],
+            )
+            .expect("forgetting cached PR");
+    }
+
+    pub fn get_issue(&self, repo: &str, number: u64, max_age: Option<i64>) -> Option<Issue> {
+        self.conn()
+            .query_row(
+                "SELECT issue FROM issue WHERE repo = ?1 AND number = ?2 AND fetched_at >= ?3",
+                params![repo, number as i64, oldest(max_age)],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .expect("reading cached issue")
+            .and_then(|i| serde_json::from_str(i.as_str()).ok())
+    }
+
+    pub fn save_issue(&self, repo: &str, issue: &Issue) {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO issue (repo, number, issue, fetched_at) VALUES (?1, ?2, ?3, ?4)",
+                params![repo, issue.number as i64, serde_json::to_string(issue).unwrap(), now()],
+            )
+            .expect("caching issue");
+    }
+
+    pub fn get_issues(&self, repo: &str, max_age: Option<i64>) -> Option<Vec<Issue>> {
+        self.conn()
+            .query_row(
+                "SELECT issues FROM issue_list WHERE repo = ?1 AND fetched_at >= ?2",
+                params![repo, oldest(max_age)],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .expect("reading cached issues")
+            .and_then(|i| serde_json::from_str(i.as_str()).ok())
+    }
+
+    pub fn save_issues(&self, repo: &str, issues: &[Issue]) {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO issue_list (repo, issues, fetched_at) VALUES (?1, ?2, ?3)",
+                params![repo, serde_json::to_string(issues).unwrap(), now()],
+            )
+            .expect("caching issues");
+    }
+
+    pub fn forget_issues(&self, repo: &str) {
+        let conn = self.conn();
+        conn.execute("DELETE FROM issue_list WHERE repo = ?1", params![repo])
+            .expect("forgetting cached issues");
+        conn.execute("DELETE FROM issue WHERE repo = ?1", params![repo])
+            .expect("forgetting cached issues");
+    }
+}
@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+};
+
+use crate::{
+    color,
+    db::{self, Db},
+};
+
+use super::{Forge, Issue, MergeOptions, Pr, PrMetadata, ReviewThread};
+
+// How long the current login is trusted, it doesn't change unless the token does.
+const LOGIN_TTL_SECS: i64 = 24 * 60 * 60;
+
+// The login for a host, looked up with `fetch` only when the cache doesn't have a recent one.
+// Without a network an old login does, and offline a missing one is just empty.
+pub(crate) async fn current_login(
+    db: &Db,
+    host: &str,
+    fetch: impl Future<Output = anyhow::Result<String>>,
+) -> String {
+    let max_age = match db::is_offline() {
+        true => None,
+        false => Some(LOGIN_TTL_SECS),
+    };
+    if let Some(login) = db.get_login(host, max_age) {
+        return login;
+    }
+    if db::is_offline() {
+        return String::new();
+    }
+    match fetch.await {
+        Ok(login) => {
+            db.save_login(host, &login);
+            login
+        }
+        Err(e) => db
+            .get_login(host, None)
+            .unwrap_or_else(|| panic!("error getting the current user: {}", e)),
+    }
+}
+
+// Serves PR and issue reads from ~/.gg/db.sqlite while they're younger than the TTL (or
+// always, offline), and forgets entries once a write has been tried, whether or not it went
+// through (a timed out request may well have).
+pub struct CachedForge {
+    inner: Box<dyn Forge>,
+    // host/org/repo
+    repo: String,
+    ttl_secs: i64,
+    db: Db,
+}
+
+impl CachedForge {
+    // `db` is the handle the backend looked its login up with, so there's one per run.
+    pub fn new(inner: Box<dyn Forge>, repo: String, ttl_secs: i64, db: Db) -> CachedForge {
+        CachedForge {
+            inner,
+            repo,
+            ttl_secs,
+            db,
+        }
+    }
+
+    fn max_age(&self) -> Option<i64> {
+        match db::is_offline() {
+            true => None,
+            false => Some(self.ttl_secs),
+        }
+    }
+
+    fn check_online(&self, what: &str) -> anyhow::Result<()> {
+        match db::is_offline() {
+            true => Err(anyhow::anyhow!("can't {} with --offline", what)),
+            false => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Forge for CachedForge {
+    fn issue_url(&self, number: u64) -> String {
+        self.inner.issue_url(number)
+    }
+
+    async fn open_pr(
+        &self,
+        full_branch: String,
+        base: String,
+        title: String,
+        body: String,
+        is_draft: bool,
+    ) -> anyhow::Result<String> {
+        self.check_online("open a PR")?;
+        let res = self
+            .inner
+            .open_pr(full_branch.clone(), base, title, body, is_draft)
+            .await;
+        self.db.forget_pr(&self.repo, &full_branch);
+        res
+    }
+
+    // PRs are looked up like this right before changing them, so only offline uses the cache.
+    async fn pr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<Pr>> {
+        if db::is_offline() {
+            let branches = HashSet::from([full_branch.to_string()]);
+            return Ok(self
+                .db
+                .get_prs(&self.repo, &branches, None)
+                .remove(full_branch)
+                .flatten()
+                .filter(|pr| pr.state == "OPEN"));
+        }
+        self.inner.pr_for_branch(full_branch).await
+    }
+
+    async fn prs_for_branches(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<Pr>> {
+        let mut cached = self.db.get_prs(&self.repo, branches, self.max_age());
+        let missing: HashSet<String> = branches
+            .iter()
+            .filter(|b| !cached.contains_key(*b))
+            .cloned()
+            .collect();
+        if !missing.is_empty() && !db::is_offline() {
+            match self.inner.prs_for_branches(&missing).await {
+                Ok(prs) => {
+                    let mut fetched: HashMap<String, Option<Pr>> =
+                        missing.into_iter().map(|b| (b, None)).collect();
+                    for pr in prs {
+                        fetched.insert(pr.branch.clone(), Some(pr));
+                    }
+                    self.db.save_prs(&self.repo, &fetched);
+                    cached.extend(fetched);
+                }
+                // Without a network, stale is better than nothing.
+                Err(e) => {
+                    let stale = self.db.get_prs(&self.repo, &missing, None);
+                    if stale.is_empty() {
+                        return Err(e);
+                    }
+                    println!("{}", color::yellow(format!("Using cached PRs: {}", e)));
+                    cached.extend(stale);
+                }
+            }
+        }
+        Ok(cached.into_values().flatten().collect())
+    }
+
+    async fn update_pr(
+        &self,
+        pr: &Pr,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.check_online("edit a PR")?;
+        let res = self.inner.update_pr(pr, title, body).await;
+        self.db.forget_pr(&self.repo, &pr.branch);
+        res
+    }
+
+    async fn add_pr_metadata(&self, pr: &Pr, metadata: &PrMetadata) -> anyhow::Result<()> {
+        self.check_online("edit a PR")?;
+        let res = self.inner.add_pr_metadata(pr, metadata).await;
+        self.db.forget_pr(&self.repo, &pr.branch);
+        res
+    }
+
+    async fn land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.check_online("land a PR")?;
+        let res = self.inner.land_pr(full_branch.clone(), opts).await;
+        self.db.forget_pr(&self.repo, &full_branch);
+        res
+    }
+
+    async fn auto_land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.check_online("auto-merge a PR")?;
+        let res = self.inner.auto_land_pr(full_branch.clone(), opts).await;
+        self.db.forget_pr(&self.repo, &full_branch);
+        res
+    }
+
+    async fn unresolved_threads(&self, pr: &Pr) -> anyhow::Result<usize> {
+        self.check_online("look up review threads")?;
+        self.inner.unresolved_threads(pr).await
+    }
+
+    async fn review_threads(&self, pr: &Pr) -> anyhow::Result<Vec<ReviewThread>> {
+        self.check_online("look up review threads")?;
+        self.inner.review_threads(pr).await
+    }
+
+    async fn change_base(&self, full_branch: String, new_base: String) -> anyhow::Result<()> {
+        self.check_online("change a PR's base")?;
+        let res = self
+            .inner
+            .change_base(full_branch.clone(), new_base)
+            .await;
+        self.db.forget_pr(&self.repo, &full_branch);
+        res
+    }
+
+    async fn create_issue(&self, title: &str, body: &str) -> anyhow::Result<Issue> {
+        self.check_online("create an issue")?;
+        let res = self.inner.create_issue(title, body).await;
+        self.db.forget_issues(&self.repo);
+        res
+    }
+
+    async fn get_issue(&self, number: u64) -> anyhow::Result<Issue> {
+        if let Some(issue) = self.db.get_issue(&self.repo, number, self.max_age()) {
+            return Ok(issue);
+        }
+        self.check_online("look up an issue that isn't cached")?;
+        let issue = self.inner.get_issue(number).await?;
+        self.db.save_issue(&self.repo, &issue);
+        Ok(issue)
+    }
+
+    async fn get_issues(&self) -> anyhow::Result<Vec<Issue>> {
+        if let Some(issues) = self.db.get_issues(&self.repo, self.max_age()) {
+            return Ok(issues);
+        }
+        self.check_online("list issues that aren't cached")?;
+        match self.inner.get_issues().await {
+            Ok(issues) => {
+                self.db.save_issues(&self.repo, &issues);
+                Ok(issues)
+            }
+            Err(e) => match self.db.get_issues(&self.repo, None) {
+                Some(issues) => Ok(issues),
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn set_issue_closed(&self, number: u64) -> anyhow::Result<()> {
+        self.check_online("close an issue")?;
+        let res = self.inner.set_issue_closed(number).await;
+        self.db.forget_issues(&self.repo);
+        res
+    }
+}
@@ -0,0 +1,80 @@
+use std::{fs, path::PathBuf};
+
+use crate::{file, git};
+
+use super::template;
+
+// The last PR title and body written in the editor for each branch, kept until the forge has
+// taken them so a failed request (or a closed terminal) doesn't lose them.
+fn draft_path(full_branch: &str) -> PathBuf {
+    git::git_path("GG_PR_DRAFTS").join(full_branch)
+}
+
+pub(crate) fn load(full_branch: &str) -> Option<String> {
+    let saved = fs::read_to_string(draft_path(full_branch)).ok()?;
+    println!("Picking up the saved draft for {}", full_branch);
+    println!("Run 'gg pr --discard-draft' to start over instead");
+    Some(saved)
+}
+
+pub(crate) fn save(full_branch: &str, buffer: &str) {
+    let path = draft_path(full_branch);
+    fs::create_dir_all(path.parent().unwrap()).expect("creating drafts dir");
+    fs::write(path, buffer).expect("saving PR draft");
+}
+
+// Whether there was a draft to throw away.
+pub(crate) fn clear(full_branch: &str) -> bool {
+    fs::remove_file(draft_path(full_branch)).is_ok()
+}
+
+// Opens the buffer in vim and saves what comes back before anything else can fail, then
+// splits it into the title and body. `from_template` also drops the template's comments.
+pub(crate) fn edit(
+    full_branch: &str,
+    buffer: String,
+    from_template: bool,
+) -> anyhow::Result<(String, String)> {
+    let res = file::open_vim(buffer);
+    save(full_branch, &res);
+    let mut res = template::cut_instructions(&res);
+    if from_template {
+        res = template::strip_instructions(&res);
+    }
+    split(&res)
+}
+
+// The first line is the title and the rest the body, which can be empty.
+fn split(buffer: &str) -> anyhow::Result<(String, String)> {
+    let (title, body) = buffer.split_once('\n').unwrap_or((buffer, ""));
+    if title.trim().is_empty() {
+        anyhow::bail!("the PR title is empty, not sending it");
+    }
+    println!("Using body: \n{}", body);
+    Ok((title.trim().to_string(), body.trim_end().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split;
+
+    #[test]
+    fn a_single_line_is_just_a_title() {
+        let (title, body) = split("Title").unwrap();
+        assert_eq!(title, "Title");
+        assert_eq!(body, "");
+    }
+
+    #[test]
+    fn keeps_the_body_after_the_title() {
+        let (title, body) = split("Title\n\nbody\n\n").unwrap();
+        assert_eq!(title, "Title");
+        assert_eq!(body, "\nbody");
+    }
+
+    #[test]
+    fn refuses_an_empty_title() {
+        assert!(split("").is_err());
+        assert!(split("  \nbody").is_err());
+    }
+}
@@ -0,0 +1,598 @@
+use async_trait::async_trait;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{config::FullConfig, db::Db};
+
+use super::{cache::current_login, Forge, Issue, MergeOptions, Pr, PrMetadata};
+
+// Page size for listing, gitea caps it at 50 by default.
+const PAGE_LIMIT: u32 = 50;
+
+// How long one listing of the open PRs answers lookups. A single gg command looks up the
+// same few branches over and over, while gg land --stack polls every 15s and needs fresh ones.
+const OPEN_PRS_REUSE: Duration = Duration::from_secs(10);
+
+// PRs and issues on a gitea (or forgejo) instance through its REST api, which takes the
+// token as a bearer token like github's.
+pub struct GiteaRepo {
+    org: String,
+    repo: String,
+    current_user: String,
+    octo: Octocrab,
+    web_url: String,
+    // Gitea can't filter PRs by head branch, so every lookup starts from this list. Cleared
+    // whenever a PR is changed.
+    open_prs: Mutex<Option<(Instant, Vec<PullRequest>)>>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct PullRequest {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    html_url: String,
+    state: String,
+    #[serde(default)]
+    merged: bool,
+    #[serde(default)]
+    mergeable: bool,
+    head: PrBranch,
+}
+
+#[derive(Deserialize, Clone)]
+struct PrBranch {
+    #[serde(rename = "ref")]
+    branch: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct CombinedStatus {
+    #[serde(default)]
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaIssue {
+    number: u64,
+    title: String,
+    html_url: String,
+    #[serde(default)]
+    labels: Vec<Label>,
+}
+
+#[derive(Deserialize)]
+struct Label {
+    id: u64,
+    name: String,
+}
+
+impl From<GiteaIssue> for Issue {
+    fn from(issue: GiteaIssue) -> Issue {
+        Issue {
+            number: issue.number,
+            title: issue.title,
+            url: issue.html_url,
+            labels: issue.labels.into_iter().map(|l| l.name).collect(),
+        }
+    }
+}
+
+impl GiteaRepo {
+    pub async fn new(cfg: FullConfig, db: &Db) -> anyhow::Result<GiteaRepo> {
+        let octo = Octocrab::builder()
+            .personal_token(cfg.token.clone())
+            .base_uri(cfg.saved.api_url()?)
+            .expect("invalid gitea api url")
+            .build()
+            .unwrap();
+        let current_user = current_login(db, &cfg.saved.host()?, async {
+            let user: User = octo
+                .get("/user", None::<&()>)
+                .await
+                .map_err(anyhow::Error::msg)?;
+            Ok(user.login)
+        })
+        .await;
+        Ok(GiteaRepo {
+            org: cfg.saved.repo_org.clone(),
+            repo: cfg.repo_name,
+            current_user,
+            octo,
+            web_url: cfg.saved.forge_web_url()?,
+            open_prs: Mutex::new(None),
+        })
+    }
+
+    fn repo_path(&self, rest: &str) -> String {
+        format!("/repos/{}/{}{}", self.org, self.repo, rest)
+    }
+
+    // Gets a whole listing, `limit` at a time, until a short page says it's the last.
+    async fn all_pages<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        params: serde_json::Value,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut items = vec![];
+        for page in 1.. {
+            let mut params = params.clone();
+            params["page"] = json!(page);
+            params["limit"] = json!(PAGE_LIMIT);
+            let batch: Vec<T> = self
+                .octo
+                .get(self.repo_path(path), Some(&params))
+                .await
+                .map_err(anyhow::Error::msg)?;
+            let done = batch.len() < PAGE_LIMIT as usize;
+            items.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    async fn open_prs(&self) -> anyhow::Result<Vec<PullRequest>> {
+        if let Some((fetched_at, prs)) = &*self.open_prs.lock().unwrap() {
+            if fetched_at.elapsed() < OPEN_PRS_REUSE {
+                return Ok(prs.clone());
+            }
+        }
+        let prs: Vec<PullRequest> = self.all_pages("/pulls", json!({ "state": "open" })).await?;
+        *self.open_prs.lock().unwrap() = Some((Instant::now(), prs.clone()));
+        Ok(prs)
+    }
+
+    fn forget_open_prs(&self) {
+        *self.open_prs.lock().unwrap() = None;
+    }
+
+    // Merged and closed PRs for `branches`, most recently updated first. Paging through all
+    // of them would cost a request per page of the repo's history, so this stops once every
+    // branch is found or a whole page goes by without any of them.
+    async fn closed_prs(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<PullRequest>> {
+        let mut found = vec![];
+        for page in 1.. {
+            let batch: Vec<PullRequest> = self
+                .octo
+                .get(
+                    self.repo_path("/pulls"),
+                    Some(&json!({
+                        "state": "closed",
+                        "sort": "recentupdate",
+                        "page": page,
+                        "limit": PAGE_LIMIT,
+                    })),
+                )
+                .await
+                .map_err(anyhow::Error::msg)?;
+            let last = batch.len() < PAGE_LIMIT as usize;
+            let before = found.len();
+            found.extend(
+                batch
+                    .into_iter()
+                    .filter(|pr| branches.contains(&pr.head.branch)),
+            );
+            let seen: HashSet<&String> = found.iter().map(|pr| &pr.head.branch).collect();
+            if last || found.len() == before || seen.len() == branches.len() {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    async fn open_pr_number(&self, full_branch: &str) -> anyhow::Result<u64> {
+        self.open_prs()
+            .await?
+            .into_iter()
+            .find(|pr| pr.head.branch == full_branch)
+            .map(|pr| pr.number)
+            .ok_or_else(|| anyhow::anyhow!("no PR found for {}", full_branch))
+    }
+
+    // With `when_checks_succeed` gitea holds the merge until the PR's checks pass.
+    async fn merge(
+        &self,
+        full_branch: &str,
+        opts: &MergeOptions,
+        when_checks_succeed: bool,
+    ) -> anyhow::Result<()> {
+        let number = self.open_pr_number(full_branch).await?;
+        self.forget_open_prs();
+        let mut params = json!({
+            "Do": opts.method.to_lowercase(),
+            "merge_when_checks_succeed": when_checks_succeed,
+        });
+        if let Some(title) = &opts.title {
+            params["MergeTitleField"] = json!(title);
+        }
+        if let Some(message) = &opts.message {
+            params["MergeMessageField"] = json!(message);
+        }
+        // The merge endpoint answers with an empty body, so skip deserializing it.
+        let res = self
+            .octo
+            ._post(
+                self.repo_path(&format!("/pulls/{}/merge", number)),
+                Some(&params),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        octocrab::map_github_error(res)
+            .await
+            .map_err(anyhow::Error::msg)?;
+        match when_checks_succeed {
+            true => println!("#{} will merge when its checks succeed", number),
+            false => println!("Merged #{}", number),
+        }
+        Ok(())
+    }
+
+    async fn to_pr(&self, pr: PullRequest) -> anyhow::Result<Pr> {
+        let state = pr_state(&pr);
+        // Only open PRs are worth the extra request for their checks.
+        let test_status = match state {
+            "OPEN" => {
+                let status: CombinedStatus = self
+                    .octo
+                    .get(
+                        self.repo_path(&format!("/commits/{}/status", pr.head.sha)),
+                        None::<&()>,
+                    )
+                    .await
+                    .map_err(anyhow::Error::msg)?;
+                match status.state.as_str() {
+                    "success" => "SUCCESS",
+                    "pending" => "PENDING",
+                    "failure" => "FAILURE",
+                    "error" => "ERROR",
+                    _ => "N/A",
+                }
+            }
+            _ => "N/A",
+        };
+        Ok(Pr {
+            id: pr.number.to_string(),
+            number: pr.number,
+            closed: state != "OPEN",
+            title: pr.title,
+            body: pr.body.unwrap_or_default(),
+            branch: pr.head.branch,
+            url: pr.html_url,
+            state: state.to_string(),
+            review_decision: None,
+            mergeable: match pr.mergeable {
+                true => "MERGEABLE",
+                false => "CONFLICTING",
+            }
+            .to_string(),
+            auto_merge_request: false,
+            test_status: test_status.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl Forge for GiteaRepo {
+    fn issue_url(&self, number: u64) -> String {
+        format!(
+            "{}/{}/{}/issues/{}",
+            self.web_url, self.org, self.repo, number
+        )
+    }
+
+    async fn open_pr(
+        &self,
+        full_branch: String,
+        base: String,
+        title: String,
+        body: String,
+        is_draft: bool,
+    ) -> anyhow::Result<String> {
+        // Gitea treats a WIP: title as a draft.
+        let title = match is_draft {
+            true => format!("WIP: {}", title),
+            false => title,
+        };
+        self.forget_open_prs();
+        let pr: PullRequest = self
+            .octo
+            .post(
+                self.repo_path("/pulls"),
+                Some(&json!({
+                    "head": full_branch,
+                    "base": base,
+                    "title": title,
+                    "body": body,
+                })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(pr.html_url)
+    }
+
+    async fn pr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<Pr>> {
+        let pr = self
+            .open_prs()
+            .await?
+            .into_iter()
+            .find(|pr| pr.head.branch == full_branch);
+        match pr {
+            Some(pr) => Ok(Some(self.to_pr(pr).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn prs_for_branches(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<Pr>> {
+        if branches.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut open = self.open_prs().await?;
+        open.retain(|pr| branches.contains(&pr.head.branch));
+        // Only branches without an open PR need their closed ones looked up.
+        let without_open: HashSet<String> = branches
+            .iter()
+            .filter(|b| !open.iter().any(|pr| pr.head.branch == **b))
+            .cloned()
+            .collect();
+        let closed = match without_open.is_empty() {
+            true => vec![],
+            false => self.closed_prs(&without_open).await?,
+        };
+        let mut prs = vec![];
+        for pr in newest_per_branch(open.into_iter().chain(closed)) {
+            prs.push(self.to_pr(pr).await?);
+        }
+        Ok(prs)
+    }
+
+    async fn update_pr(
+        &self,
+        pr: &Pr,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.forget_open_prs();
+        let mut params = json!({});
+        if let Some(title) = title {
+            params["title"] = json!(title);
+        }
+        if let Some(body) = body {
+            params["body"] = json!(body);
+        }
+        let _pr: PullRequest = self
+            .octo
+            .patch(
+                self.repo_path(&format!("/pulls/{}", pr.number)),
+                Some(&params),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+
+    async fn add_pr_metadata(&self, pr: &Pr, metadata: &PrMetadata) -> anyhow::Result<()> {
+        let (teams, users): (Vec<&String>, Vec<&String>) = metadata
+            .reviewers
+            .iter()
+            .filter(|r| **r != self.current_user)
+            .partition(|r| r.contains('/'));
+        // A reviewer gitea doesn't know shouldn't cost the labels and assignees, so that
+        // error waits until they're on.
+        let mut reviewers_res = Ok(());
+        if !teams.is_empty() || !users.is_empty() {
+            // Teams are asked for by name alone, they're always the repo org's.
+            let teams: Vec<&str> = teams
+                .iter()
+                .filter_map(|t| t.split_once('/'))
+                .map(|(_, team)| team)
+                .collect();
+            reviewers_res = self
+                .octo
+                .post::<_, serde_json::Value>(
+                    self.repo_path(&format!("/pulls/{}/requested_reviewers", pr.number)),
+                    Some(&json!({ "reviewers": users, "team_reviewers": teams })),
+                )
+                .await
+                .map(|_| ())
+                .map_err(anyhow::Error::msg);
+        }
+        if !metadata.labels.is_empty() {
+            let labels: Vec<Label> = self.all_pages("/labels", json!({})).await?;
+            let ids = label_ids(labels, &metadata.labels);
+            let _res: serde_json::Value = self
+                .octo
+                .post(
+                    self.repo_path(&format!("/issues/{}/labels", pr.number)),
+                    Some(&json!({ "labels": ids })),
+                )
+                .await
+                .map_err(anyhow::Error::msg)?;
+        }
+        if !metadata.assignees.is_empty() {
+            let _issue: GiteaIssue = self
+                .octo
+                .patch(
+                    self.repo_path(&format!("/issues/{}", pr.number)),
+                    Some(&json!({ "assignees": metadata.assignees })),
+                )
+                .await
+                .map_err(anyhow::Error::msg)?;
+        }
+        reviewers_res
+    }
+
+    async fn land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.merge(&full_branch, opts, false).await
+    }
+
+    async fn auto_land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.merge(&full_branch, opts, true).await
+    }
+
+    async fn change_base(&self, full_branch: String, new_base: String) -> anyhow::Result<()> {
+        let number = self.open_pr_number(&full_branch).await?;
+        self.forget_open_prs();
+        let _pr: PullRequest = self
+            .octo
+            .patch(
+                self.repo_path(&format!("/pulls/{}", number)),
+                Some(&json!({ "base": new_base })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+
+    async fn create_issue(&self, title: &str, body: &str) -> anyhow::Result<Issue> {
+        let issue: GiteaIssue = self
+            .octo
+            .post(
+                self.repo_path("/issues"),
+                Some(&json!({ "title": title, "body": body })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(issue.into())
+    }
+
+    async fn get_issue(&self, number: u64) -> anyhow::Result<Issue> {
+        let issue: GiteaIssue = self
+            .octo
+            .get(self.repo_path(&format!("/issues/{}", number)), None::<&()>)
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(issue.into())
+    }
+
+    async fn get_issues(&self) -> anyhow::Result<Vec<Issue>> {
+        let issues: Vec<GiteaIssue> = self
+            .all_pages(
+                "/issues",
+                json!({
+                    "state": "open",
+                    "type": "issues",
+                    "created_by": self.current_user,
+                }),
+            )
+            .await?;
+        Ok(issues.into_iter().map(Issue::from).collect())
+    }
+
+    async fn set_issue_closed(&self, number: u64) -> anyhow::Result<()> {
+        let _issue: GiteaIssue = self
+            .octo
+            .patch(
+                self.repo_path(&format!("/issues/{}", number)),
+                Some(&json!({ "state": "closed" })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+}
+
+fn pr_state(pr: &PullRequest) -> &'static str {
+    match (pr.state.as_str(), pr.merged) {
+        (_, true) => "MERGED",
+        ("open", _) => "OPEN",
+        _ => "CLOSED",
+    }
+}
+
+// The highest numbered PR for each branch, like github's newest first.
+fn newest_per_branch(prs: impl Iterator<Item = PullRequest>) -> Vec<PullRequest> {
+    let mut newest: HashMap<String, PullRequest> = HashMap::new();
+    for pr in prs {
+        match newest.get(&pr.head.branch) {
+            Some(seen) if seen.number > pr.number => {}
+            _ => {
+                newest.insert(pr.head.branch.clone(), pr);
+            }
+        }
+    }
+    newest.into_values().collect()
+}
+
+// Labels are added by id, the ones the repo doesn't have are skipped.
+fn label_ids(labels: Vec<Label>, wanted: &[String]) -> Vec<u64> {
+    labels
+        .into_iter()
+        .filter(|l| wanted.contains(&l.name))
+        .map(|l| l.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{label_ids, newest_per_branch, pr_state, Label, PrBranch, PullRequest};
+
+    fn pr(number: u64, branch: &str, state: &str, merged: bool) -> PullRequest {
+        PullRequest {
+            number,
+            title: String::new(),
+            body: None,
+            html_url: String::new(),
+            state: state.to_string(),
+            merged,
+            mergeable: true,
+            head: PrBranch {
+                branch: branch.to_string(),
+                sha: String::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn maps_states_like_github() {
+        assert_eq!(pr_state(&pr(1, "a", "open", false)), "OPEN");
+        assert_eq!(pr_state(&pr(1, "a", "closed", true)), "MERGED");
+        assert_eq!(pr_state(&pr(1, "a", "closed", false)), "CLOSED");
+    }
+
+    #[test]
+    fn keeps_the_newest_pr_per_branch() {
+        let prs = vec![
+            pr(3, "a", "closed", false),
+            pr(7, "a", "open", false),
+            pr(5, "b", "closed", true),
+            pr(2, "b", "closed", false),
+        ];
+        let mut newest: Vec<(String, u64)> = newest_per_branch(prs.into_iter())
+            .into_iter()
+            .map(|pr| (pr.head.branch, pr.number))
+            .collect();
+        newest.sort();
+        assert_eq!(newest, vec![("a".to_string(), 7), ("b".to_string(), 5)]);
+    }
+
+    #[test]
+    fn looks_up_label_ids_by_name() {
+        let labels = vec![
+            Label {
+                id: 1,
+                name: "bug".to_string(),
+            },
+            Label {
+                id: 2,
+                name: "docs".to_string(),
+            },
+        ];
+        let wanted = vec!["docs".to_string(), "missing".to_string()];
+        assert_eq!(label_ids(labels, &wanted), vec![2]);
+    }
+}
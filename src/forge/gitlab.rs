@@ -0,0 +1,583 @@
+use async_trait::async_trait;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::FullConfig,
+    db::Db,
+    github::graphql::{run_query, Connection},
+};
+
+use super::{
+    cache::current_login, split_origin, Forge, Issue, MergeOptions, Pr, PrMetadata, ReviewComment,
+    ReviewThread,
+};
+
+// Merge requests (and issues) on a gitlab instance. The REST and graphql apis both take the
+// token as a bearer token, so octocrab's generic client does the requests.
+pub struct GitlabRepo {
+    // org/repo, url encoded, which is how the REST api names a project.
+    project: String,
+    full_path: String,
+    current_user: String,
+    octo: Octocrab,
+    graphql_octo: Octocrab,
+    graphql_path: String,
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct User {
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct UserId {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    iid: u64,
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabIssue {
+    iid: u64,
+    title: String,
+    web_url: String,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+impl From<GitlabIssue> for Issue {
+    fn from(issue: GitlabIssue) -> Issue {
+        Issue {
+            number: issue.iid,
+            title: issue.title,
+            url: issue.web_url,
+            labels: issue.labels,
+        }
+    }
+}
+
+// Gitlab's largest page for rest lists.
+const PER_PAGE: usize = 100;
+
+const MR_FIELDS: &str = "
+    id
+    iid
+    title
+    description
+    webUrl
+    state
+    sourceBranch
+    approved
+    conflicts
+    autoMergeEnabled
+    headPipeline {
+        status
+    }
+";
+
+#[derive(Deserialize)]
+struct ProjectData {
+    project: Option<ProjectNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectNode {
+    merge_requests: Connection<MergeRequestNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeRequestNode {
+    id: String,
+    iid: String,
+    title: String,
+    description: Option<String>,
+    web_url: String,
+    state: String,
+    source_branch: String,
+    approved: bool,
+    conflicts: bool,
+    auto_merge_enabled: bool,
+    head_pipeline: Option<PipelineNode>,
+}
+
+#[derive(Deserialize)]
+struct PipelineNode {
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct Discussion {
+    id: String,
+    notes: Vec<Note>,
+}
+
+#[derive(Deserialize)]
+struct Note {
+    id: u64,
+    body: String,
+    author: User,
+    // Notes gitlab writes itself, like "added 1 commit".
+    #[serde(default)]
+    system: bool,
+    #[serde(default)]
+    resolvable: bool,
+    #[serde(default)]
+    resolved: bool,
+    #[serde(default)]
+    position: Option<NotePosition>,
+}
+
+#[derive(Deserialize)]
+struct NotePosition {
+    new_path: Option<String>,
+    old_path: Option<String>,
+    new_line: Option<u64>,
+    old_line: Option<u64>,
+    head_sha: Option<String>,
+}
+
+impl From<MergeRequestNode> for Pr {
+    fn from(node: MergeRequestNode) -> Pr {
+        let state = match node.state.as_str() {
+            "opened" => "OPEN",
+            "merged" => "MERGED",
+            _ => "CLOSED",
+        };
+        let test_status = match node.head_pipeline.map(|p| p.status) {
+            None => "N/A",
+            Some(status) => match status.as_str() {
+                "SUCCESS" => "SUCCESS",
+                "FAILED" => "FAILURE",
+                "CANCELED" | "SKIPPED" => "ERROR",
+                _ => "PENDING",
+            },
+        };
+        Pr {
+            id: node.id,
+            number: node.iid.parse().unwrap_or_default(),
+            closed: state != "OPEN",
+            title: node.title,
+            body: node.description.unwrap_or_default(),
+            branch: node.source_branch,
+            url: node.web_url,
+            state: state.to_string(),
+            review_decision: Some(
+                match node.approved {
+                    true => "APPROVED",
+                    false => "REVIEW_REQUIRED",
+                }
+                .to_string(),
+            ),
+            mergeable: match node.conflicts {
+                true => "CONFLICTING",
+                false => "MERGEABLE",
+            }
+            .to_string(),
+            auto_merge_request: node.auto_merge_enabled,
+            test_status: test_status.to_string(),
+        }
+    }
+}
+
+impl GitlabRepo {
+    pub async fn new(cfg: FullConfig, db: &Db) -> anyhow::Result<GitlabRepo> {
+        let octo = Octocrab::builder()
+            .personal_token(cfg.token.clone())
+            .base_uri(cfg.saved.gitlab_api_url()?)
+            .expect("invalid gitlab api url")
+            .build()
+            .unwrap();
+        let (graphql_origin, graphql_path) = split_origin(&cfg.saved.gitlab_graphql_url()?);
+        let graphql_octo = Octocrab::builder()
+            .personal_token(cfg.token)
+            .base_uri(graphql_origin)
+            .expect("invalid gitlab graphql url")
+            .build()
+            .unwrap();
+        let current_user = current_login(db, &cfg.saved.host()?, async {
+            let user: User = octo
+                .get("/user", None::<&()>)
+                .await
+                .map_err(anyhow::Error::msg)?;
+            Ok(user.username)
+        })
+        .await;
+        let full_path = format!("{}/{}", cfg.saved.repo_org, cfg.repo_name);
+        Ok(GitlabRepo {
+            project: full_path.replace('/', "%2F"),
+            full_path,
+            current_user,
+            octo,
+            graphql_octo,
+            graphql_path,
+            web_url: cfg.saved.forge_web_url()?,
+        })
+    }
+
+    fn project_path(&self, rest: &str) -> String {
+        format!("/projects/{}{}", self.project, rest)
+    }
+
+    async fn open_mr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<MergeRequest>> {
+        let mrs: Vec<MergeRequest> = self
+            .octo
+            .get(
+                self.project_path("/merge_requests"),
+                Some(&json!({ "source_branch": full_branch, "state": "opened" })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(mrs.into_iter().next())
+    }
+
+    async fn discussions(&self, pr: &Pr) -> anyhow::Result<Vec<Discussion>> {
+        let mut discussions = vec![];
+        for page in 1.. {
+            let batch: Vec<Discussion> = self
+                .octo
+                .get(
+                    self.project_path(&format!("/merge_requests/{}/discussions", pr.number)),
+                    Some(&json!({ "per_page": PER_PAGE, "page": page })),
+                )
+                .await
+                .map_err(anyhow::Error::msg)?;
+            let done = batch.len() < PER_PAGE;
+            discussions.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(discussions)
+    }
+
+    async fn user_ids(&self, usernames: &[String]) -> anyhow::Result<Vec<u64>> {
+        let mut ids = vec![];
+        for username in usernames {
+            let users: Vec<UserId> = self
+                .octo
+                .get("/users", Some(&json!({ "username": username })))
+                .await
+                .map_err(anyhow::Error::msg)?;
+            let user = users
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("no user {}", username))?;
+            ids.push(user.id);
+        }
+        Ok(ids)
+    }
+
+    // One query for all the branches (paged past the first 100 MRs), newest first so the first
+    // MR seen for a branch wins. `state` is a MergeRequestState, `opened` or `all`.
+    async fn newest_mrs(&self, branches: &HashSet<String>, state: &str) -> anyhow::Result<Vec<Pr>> {
+        if branches.is_empty() {
+            return Ok(vec![]);
+        }
+        let query = format!(
+            "query($path: ID!, $branches: [String!], $cursor: String) {{ project(fullPath: $path) {{ mergeRequests(sourceBranches: $branches, state: {}, sort: CREATED_DESC, first: 100, after: $cursor) {{ pageInfo {{ hasNextPage endCursor }} nodes {{ {} }} }} }} }}",
+            state, MR_FIELDS
+        );
+        let mut newest: HashMap<String, Pr> = HashMap::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: ProjectData = run_query(
+                &self.graphql_octo,
+                self.graphql_path.as_str(),
+                query.as_str(),
+                json!({ "path": self.full_path, "branches": branches, "cursor": cursor }),
+            )
+            .await?;
+            let mrs = data
+                .project
+                .ok_or_else(|| anyhow::anyhow!("no project {}", self.full_path))?
+                .merge_requests;
+            cursor = mrs.next_cursor();
+            for node in mrs.into_nodes() {
+                newest
+                    .entry(node.source_branch.clone())
+                    .or_insert_with(|| node.into());
+            }
+            if cursor.is_none() {
+                return Ok(newest.into_values().collect());
+            }
+        }
+    }
+
+    // With `when_pipeline_succeeds` gitlab holds the merge until the pipeline passes.
+    // Gitlab takes the commit message as one string, the title is its first line. Whether a
+    // non-squash merge makes a merge commit or rebases is the project's merge method setting.
+    async fn merge(
+        &self,
+        full_branch: &str,
+        opts: &MergeOptions,
+        when_pipeline_succeeds: bool,
+    ) -> anyhow::Result<()> {
+        let mr = self
+            .open_mr_for_branch(full_branch)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no merge request for {}", full_branch))?;
+        let squash = opts.method == "SQUASH";
+        let mut params = json!({
+            "squash": squash,
+            "merge_when_pipeline_succeeds": when_pipeline_succeeds,
+        });
+        if let Some(title) = &opts.title {
+            let message = match &opts.message {
+                Some(message) => format!("{}\n\n{}", title, message),
+                None => title.clone(),
+            };
+            let field = match squash {
+                true => "squash_commit_message",
+                false => "merge_commit_message",
+            };
+            params[field] = json!(message);
+        }
+        let _res: serde_json::Value = self
+            .octo
+            .put(
+                self.project_path(&format!("/merge_requests/{}/merge", mr.iid)),
+                Some(&params),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        match when_pipeline_succeeds {
+            true => println!("{} will merge when its pipeline succeeds", mr.web_url),
+            false => println!("Merged {}", mr.web_url),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Forge for GitlabRepo {
+    fn issue_url(&self, number: u64) -> String {
+        format!("{}/{}/-/issues/{}", self.web_url, self.full_path, number)
+    }
+
+    async fn open_pr(
+        &self,
+        full_branch: String,
+        base: String,
+        title: String,
+        body: String,
+        is_draft: bool,
+    ) -> anyhow::Result<String> {
+        let title = match is_draft {
+            true => format!("Draft: {}", title),
+            false => title,
+        };
+        let mr: MergeRequest = self
+            .octo
+            .post(
+                self.project_path("/merge_requests"),
+                Some(&json!({
+                    "source_branch": full_branch,
+                    "target_branch": base,
+                    "title": title,
+                    "description": body,
+                })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(mr.web_url)
+    }
+
+    async fn pr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<Pr>> {
+        let branches = HashSet::from([full_branch.to_string()]);
+        Ok(self
+            .newest_mrs(&branches, "opened")
+            .await?
+            .into_iter()
+            .next())
+    }
+
+    async fn prs_for_branches(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<Pr>> {
+        self.newest_mrs(branches, "all").await
+    }
+
+    async fn update_pr(
+        &self,
+        pr: &Pr,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()> {
+        let mut params = json!({});
+        if let Some(title) = title {
+            params["title"] = json!(title);
+        }
+        if let Some(body) = body {
+            params["description"] = json!(body);
+        }
+        let _res: serde_json::Value = self
+            .octo
+            .put(
+                self.project_path(&format!("/merge_requests/{}", pr.number)),
+                Some(&params),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+
+    // Gitlab can't request a review from a group, so `org/team` reviewers are skipped. The
+    // MR is new, so setting reviewers and assignees (rather than adding to them) is fine.
+    async fn add_pr_metadata(&self, pr: &Pr, metadata: &PrMetadata) -> anyhow::Result<()> {
+        let reviewers: Vec<String> = metadata
+            .reviewers
+            .iter()
+            .filter(|r| !r.contains('/') && **r != self.current_user)
+            .cloned()
+            .collect();
+        let mut params = json!({});
+        if !reviewers.is_empty() {
+            params["reviewer_ids"] = json!(self.user_ids(&reviewers).await?);
+        }
+        if !metadata.assignees.is_empty() {
+            params["assignee_ids"] = json!(self.user_ids(&metadata.assignees).await?);
+        }
+        if !metadata.labels.is_empty() {
+            params["add_labels"] = json!(metadata.labels.join(","));
+        }
+        let _res: serde_json::Value = self
+            .octo
+            .put(
+                self.project_path(&format!("/merge_requests/{}", pr.number)),
+                Some(&params),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+
+    async fn land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.merge(&full_branch, opts, false).await
+    }
+
+    async fn auto_land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        self.merge(&full_branch, opts, true).await
+    }
+
+    // A discussion is resolved along with its first note.
+    async fn unresolved_threads(&self, pr: &Pr) -> anyhow::Result<usize> {
+        Ok(self
+            .discussions(pr)
+            .await?
+            .iter()
+            .filter_map(|d| d.notes.first())
+            .filter(|n| n.resolvable && !n.resolved)
+            .count())
+    }
+
+    // Discussions on the diff come first, like github's review threads, then the general ones.
+    async fn review_threads(&self, pr: &Pr) -> anyhow::Result<Vec<ReviewThread>> {
+        let mut threads: Vec<ReviewThread> = self
+            .discussions(pr)
+            .await?
+            .into_iter()
+            .filter_map(|d| {
+                let first = d.notes.iter().find(|n| !n.system)?;
+                let position = first.position.as_ref();
+                let resolved = first.resolvable && first.resolved;
+                Some(ReviewThread {
+                    path: position.and_then(|p| p.new_path.clone().or_else(|| p.old_path.clone())),
+                    line: position.and_then(|p| p.new_line.or(p.old_line)),
+                    commit: position.and_then(|p| p.head_sha.clone()),
+                    resolved,
+                    diff_hunk: None,
+                    comments: d
+                        .notes
+                        .into_iter()
+                        .filter(|n| !n.system)
+                        .map(|n| ReviewComment {
+                            id: n.id.to_string(),
+                            author: n.author.username,
+                            body: n.body,
+                            url: format!("{}#note_{}", pr.url, n.id),
+                        })
+                        .collect(),
+                    id: d.id,
+                })
+            })
+            .collect();
+        threads.sort_by_key(|t| t.path.is_none());
+        Ok(threads)
+    }
+
+    async fn change_base(&self, full_branch: String, new_base: String) -> anyhow::Result<()> {
+        let mr = self
+            .open_mr_for_branch(&full_branch)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no merge request for {}", full_branch))?;
+        let _res: serde_json::Value = self
+            .octo
+            .put(
+                self.project_path(&format!("/merge_requests/{}", mr.iid)),
+                Some(&json!({ "target_branch": new_base })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+
+    async fn create_issue(&self, title: &str, body: &str) -> anyhow::Result<Issue> {
+        let issue: GitlabIssue = self
+            .octo
+            .post(
+                self.project_path("/issues"),
+                Some(&json!({ "title": title, "description": body })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(issue.into())
+    }
+
+    async fn get_issue(&self, number: u64) -> anyhow::Result<Issue> {
+        let issue: GitlabIssue = self
+            .octo
+            .get(
+                self.project_path(&format!("/issues/{}", number)),
+                None::<&()>,
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(issue.into())
+    }
+
+    async fn get_issues(&self) -> anyhow::Result<Vec<Issue>> {
+        let issues: Vec<GitlabIssue> = self
+            .octo
+            .get(
+                self.project_path("/issues"),
+                Some(&json!({
+                    "author_username": self.current_user,
+                    "state": "opened",
+                    "per_page": 100,
+                })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(issues.into_iter().map(Issue::from).collect())
+    }
+
+    async fn set_issue_closed(&self, number: u64) -> anyhow::Result<()> {
+        let _res: serde_json::Value = self
+            .octo
+            .put(
+                self.project_path(&format!("/issues/{}", number)),
+                Some(&json!({ "state_event": "close" })),
+            )
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+pub mod cache;
+pub mod draft;
+pub mod gitea;
+pub mod gitlab;
+pub mod stack_nav;
+pub mod template;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, process::Command, str::from_utf8};
+
+use crate::{
+    codeowners, color,
+    config::{self, get_full_config, FullConfig},
+    db::Db,
+    git,
+    github::GithubRepo,
+    hooks::{self, Hook, HookPayload},
+    record,
+};
+
+use self::{cache::CachedForge, gitea::GiteaRepo, gitlab::GitlabRepo};
+
+// A PR (or merge request). States use github's vocabulary whichever forge it came from:
+// state is OPEN/MERGED/CLOSED, review_decision APPROVED/CHANGES_REQUESTED/REVIEW_REQUIRED,
+// mergeable MERGEABLE/CONFLICTING/UNKNOWN and test_status SUCCESS/PENDING/FAILURE/ERROR
+// (or N/A when nothing ran).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct Pr {
+    // The forge's own id for the PR, github's graphql node id for example.
+    pub id: String,
+    pub number: u64,
+    pub closed: bool,
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    pub branch: String,
+    pub url: String,
+    pub state: String,
+    pub review_decision: Option<String>,
+    pub mergeable: String,
+    pub auto_merge_request: bool,
+    pub test_status: String,
+}
+
+impl Pr {
+    // Whether the forge is going to merge the PR by itself, for showing next to it.
+    pub fn merge_state(&self) -> Option<String> {
+        match self.auto_merge_request {
+            true => Some("auto-merge".to_string()),
+            false => None,
+        }
+    }
+}
+
+// How to merge a PR. method is github's MERGE, SQUASH or REBASE whichever forge it's for,
+// and a title or message left out falls back to the forge's own.
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    pub method: String,
+    pub title: Option<String>,
+    pub message: Option<String>,
+}
+
+// A review thread on a PR, or one of its general comments (which has no path and is never
+// resolved).
+#[derive(Debug, Clone)]
+pub struct ReviewThread {
+    pub id: String,
+    pub path: Option<String>,
+    pub line: Option<u64>,
+    // The commit the thread was started on.
+    pub commit: Option<String>,
+    pub resolved: bool,
+    pub diff_hunk: Option<String>,
+    pub comments: Vec<ReviewComment>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReviewComment {
+    pub id: String,
+    pub author: String,
+    pub body: String,
+    pub url: String,
+}
+
+// Reviewers are logins or `org/team`s, assignees are logins.
+#[derive(Debug, Clone, Default)]
+pub struct PrMetadata {
+    pub reviewers: Vec<String>,
+    pub labels: Vec<String>,
+    pub assignees: Vec<String>,
+}
+
+impl PrMetadata {
+    fn is_empty(&self) -> bool {
+        self.reviewers.is_empty() && self.labels.is_empty() && self.assignees.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+// Everything the stacked workflow needs from wherever the repo is hosted.
+#[async_trait]
+pub trait Forge: Send + Sync {
+    fn issue_url(&self, number: u64) -> String;
+
+    // Opens a PR and returns its url.
+    async fn open_pr(
+        &self,
+        full_branch: String,
+        base: String,
+        title: String,
+        body: String,
+        is_draft: bool,
+    ) -> anyhow::Result<String>;
+
+    // The open PR for a branch, if there is one.
+    async fn pr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<Pr>>;
+
+    // The newest PR for each branch that has one, in any state.
+    async fn prs_for_branches(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<Pr>>;
+
+    // Changes the PR's title and body, whichever of them are given.
+    async fn update_pr(
+        &self,
+        pr: &Pr,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()>;
+
+    // Requests reviews from, labels and assigns the PR. Anything already there is kept.
+    async fn add_pr_metadata(&self, pr: &Pr, metadata: &PrMetadata) -> anyhow::Result<()>;
+
+    async fn land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()>;
+
+    // Has the forge merge the PR once its checks pass (and it's approved), through the merge
+    // queue where the base branch has one.
+    async fn auto_land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()>;
+
+    // How many review threads on the PR are still open. Forges that can't tell report none.
+    async fn unresolved_threads(&self, _pr: &Pr) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
+    // The PR's review threads, resolved or not, followed by its general comments.
+    async fn review_threads(&self, _pr: &Pr) -> anyhow::Result<Vec<ReviewThread>> {
+        Err(anyhow::anyhow!("review threads aren't supported on this forge"))
+    }
+
+    async fn change_base(&self, full_branch: String, new_base: String) -> anyhow::Result<()>;
+
+    async fn create_issue(&self, title: &str, body: &str) -> anyhow::Result<Issue>;
+
+    async fn get_issue(&self, number: u64) -> anyhow::Result<Issue>;
+
+    // Open issues created by the current user.
+    async fn get_issues(&self) -> anyhow::Result<Vec<Issue>>;
+
+    async fn set_issue_closed(&self, number: u64) -> anyhow::Result<()>;
+
+    async fn create_pr(
+        &self,
+        full_branch: String,
+        base: Option<String>,
+        is_draft: bool,
+        requested: PrMetadata,
+        template_choice: Option<Option<String>>,
+    ) -> anyhow::Result<()> {
+        if let Some(pr) = self.pr_for_branch(&full_branch).await? {
+            println!("PR Already exists! {}", pr.url);
+            println!("Run 'gg pr --edit' to change its title and body");
+            return Ok(());
+        }
+        hooks::run(
+            Hook::PreSubmit,
+            HookPayload::for_branch(&git::parse_branch(full_branch.clone()), None),
+        )?;
+        let base = base.unwrap_or(get_full_config().saved.repo_main_branch);
+
+        let buffer = match draft::load(&full_branch) {
+            Some(saved) => saved,
+            None => self.template_for_pr(base.clone(), template_choice).await?,
+        };
+        let (title, body) = draft::edit(&full_branch, buffer, true)?;
+        let url = self
+            .open_pr(full_branch.clone(), base, title, body, is_draft)
+            .await?;
+        draft::clear(&full_branch);
+        println!("Created PR: {}", url);
+
+        // The PR is up by now, so anything going wrong from here only gets a warning.
+        let metadata = self.metadata_for_pr(&full_branch, requested).await;
+        if !metadata.is_empty() {
+            let res = match self.pr_for_branch(&full_branch).await {
+                Ok(Some(pr)) => self.add_pr_metadata(&pr, &metadata).await,
+                Ok(None) => Err(anyhow::anyhow!("no PR found for {}", full_branch)),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                println!(
+                    "{}",
+                    color::yellow(format!("Couldn't add reviewers, labels or assignees: {}", e))
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Opens the PR's current title and body in the editor and saves the result back.
+    async fn edit_pr(&self, full_branch: String) -> anyhow::Result<()> {
+        let pr = self
+            .pr_for_branch(&full_branch)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no PR found for {}", full_branch))?;
+        let buffer = draft::load(&full_branch).unwrap_or_else(|| {
+            format!(
+                "{}\n{}\n\n{}",
+                pr.title,
+                pr.body,
+                template::instructions(false)
+            )
+        });
+        let (title, body) = draft::edit(&full_branch, buffer, false)?;
+        self.update_pr(&pr, Some(title), Some(body)).await?;
+        draft::clear(&full_branch);
+        println!("Updated PR: {}", pr.url);
+        Ok(())
+    }
+
+    // What was asked for on the command line, plus the owners of the changed files, the
+    // configured defaults and the linked issue's labels.
+    async fn metadata_for_pr(&self, full_branch: &str, requested: PrMetadata) -> PrMetadata {
+        let cfg = get_full_config().saved;
+        let branch = git::parse_branch(full_branch.to_string());
+        let mut metadata = requested;
+        metadata.reviewers.extend(codeowners::owners_of(&git::changed_files(
+            &branch.start(),
+            &branch.full(),
+        )));
+        metadata.labels.extend(cfg.default_labels);
+        metadata.assignees.extend(cfg.default_assignees);
+        if let Some(issue) = cfg.linked_issue.filter(|i| *i > 0) {
+            match self.get_issue(issue).await {
+                Ok(issue) => metadata.labels.extend(issue.labels),
+                Err(e) => println!("{}", color::yellow(format!("Couldn't copy issue labels: {}", e))),
+            }
+        }
+        for list in [
+            &mut metadata.reviewers,
+            &mut metadata.labels,
+            &mut metadata.assignees,
+        ] {
+            let mut seen = HashSet::new();
+            list.retain(|x| seen.insert(x.clone()));
+        }
+        metadata
+    }
+
+    // The editor buffer for a new PR: the first commit's subject as the title, then the repo's
+    // PR template with the rest of the log filled in, or just the log if there's no template.
+    async fn template_for_pr(
+        &self,
+        base: String,
+        choice: Option<Option<String>>,
+    ) -> anyhow::Result<String> {
+        let log = git_log_from_base_branch(base);
+        let mut buffer = match template::repo_template(choice)? {
+            Some(repo_template) => {
+                let (title, rest) = log.split_once('\n').unwrap_or((log.as_str(), ""));
+                format!("{}\n\n{}", title, template::fill(&repo_template, rest))
+            }
+            None => log,
+        };
+        match get_full_config().saved.linked_issue {
+            Some(0) => {}
+            None => {}
+            Some(x) => {
+                let issue = self.get_issue(x).await.unwrap();
+                buffer.push_str(
+                    format!(
+                        "\n\nResolves Issue: [{}]({})",
+                        issue.title,
+                        self.issue_url(x)
+                    )
+                    .as_str(),
+                );
+            }
+        }
+        buffer.push_str("\n\n");
+        buffer.push_str(&template::instructions(true));
+        Ok(buffer)
+    }
+
+    async fn list_issues(&self) -> anyhow::Result<()> {
+        for issue in self.get_issues().await? {
+            println!("{} : {}", color::blue(issue.url), color::bold(issue.title));
+        }
+        Ok(())
+    }
+
+    async fn close_issue(&self, number: u64) -> anyhow::Result<()> {
+        let issue = self.get_issue(number).await?;
+        self.set_issue_closed(number).await?;
+
+        record::write_status(format!("Closed: {}", issue.title), false);
+        let cfg = get_full_config();
+        if cfg.saved.linked_issue == Some(issue.number) {
+            config::clear_selected_issue();
+        }
+        let payload =
+            HookPayload::for_branch(&git::current_parsed_branch(), None).with_issue(number);
+        if let Err(e) = hooks::run(Hook::PostCloseIssue, payload) {
+            println!("{}", e);
+        }
+        Ok(())
+    }
+}
+
+// A forge that can't be set up (a bad url in the config, say) leaves nothing to do, so like
+// a missing token this exits with the reason.
+pub async fn connect(cfg: FullConfig) -> Box<dyn Forge> {
+    match try_connect(cfg).await {
+        Ok(forge) => forge,
+        Err(e) => {
+            eprintln!("{}", color::red(e.to_string()));
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn try_connect(cfg: FullConfig) -> anyhow::Result<Box<dyn Forge>> {
+    let repo = format!(
+        "{}/{}/{}",
+        cfg.saved.host()?,
+        cfg.saved.repo_org,
+        cfg.repo_name
+    );
+    let ttl_secs = cfg.saved.cache_ttl_secs;
+    let db = Db::open();
+    let inner: Box<dyn Forge> = match cfg.saved.forge.as_str() {
+        config::GITLAB => Box::new(GitlabRepo::new(cfg, &db).await?),
+        config::GITEA => Box::new(GiteaRepo::new(cfg, &db).await?),
+        _ => Box::new(GithubRepo::new(cfg, &db).await?),
+    };
+    Ok(Box::new(CachedForge::new(inner, repo, ttl_secs, db)))
+}
+
+// For the commands built on github's own api (PR updates, review threads), which the other
+// forges don't have. Stops before a gitlab or gitea token gets sent to github.
+pub async fn connect_github(cfg: FullConfig) -> GithubRepo {
+    if cfg.saved.forge != config::GITHUB {
+        eprintln!(
+            "{}",
+            color::red(format!(
+                "This is github only, the repo is on {}",
+                cfg.saved.forge
+            ))
+        );
+        std::process::exit(1);
+    }
+    GithubRepo::new(cfg, &Db::open()).await.unwrap_or_else(|e| {
+        eprintln!("{}", color::red(e.to_string()));
+        std::process::exit(1);
+    })
+}
+
+fn git_log_from_base_branch(core_branch: String) -> String {
+    let out = match Command::new("git")
+        .arg("log")
+        .arg("--pretty=%s%n%+b")
+        .arg(format!("origin/{}..HEAD", core_branch))
+        .output()
+    {
+        Ok(output) => output,
+        Err(_e) => panic!("error!"),
+    };
+    let x: &[_] = &[' ', '\t', '\n', '\r'];
+    let result = from_utf8(&out.stdout).expect("msg").trim_end_matches(x);
+    result.to_string()
+}
+
+pub fn print_pr(pr: Option<&Pr>, branch: String) {
+    let (state, url, title) = match pr {
+        Some(p) => (
+            match p.state.as_str() {
+                "OPEN" => color::green("Open"),
+                "MERGED" => color::blue("Merged"),
+                "CLOSED" => color::red("Closed"),
+                _ => color::red("Unknown"),
+            },
+            color::blue(p.url.as_str()),
+            p.title.clone(),
+        ),
+        None => (color::white("N/A"), color::white("N/A"), "".to_string()),
+    };
+    println!(
+        "{}\t{}\t{}\t{}",
+        color::blue(branch),
+        color::bold(state),
+        url,
+        title,
+    )
+}
+
+// Splits `https://host/some/path` into `https://host` and `/some/path`.
+pub(crate) fn split_origin(url: &str) -> (String, String) {
+    let after_scheme = url.find("://").map(|i| i + 3).unwrap_or(0);
+    match url[after_scheme..].find('/') {
+        Some(i) => (
+            url[..after_scheme + i].to_string(),
+            url[after_scheme + i..].to_string(),
+        ),
+        None => (url.to_string(), "/".to_string()),
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{color, db, git};
+
+use super::{Forge, Pr};
+
+// The stack section of a PR body sits between these, everything outside them is the author's.
+const START_MARKER: &str = "<!-- gg-stack:start -->";
+const END_MARKER: &str = "<!-- gg-stack:end -->";
+
+// Rewrites the stack section of every open PR in the stack, for after its parts are pushed or
+// get new PRs. A failure only gets a warning, the push itself already went through.
+pub async fn update(forge: &dyn Forge, base: &str) {
+    if db::is_offline() {
+        return;
+    }
+    if let Err(e) = update_prs(forge, base).await {
+        println!(
+            "{}",
+            color::yellow(format!("Couldn't update the stack in PR bodies: {}", e))
+        );
+    }
+}
+
+async fn update_prs(forge: &dyn Forge, base: &str) -> anyhow::Result<()> {
+    let parts: Vec<String> = git::get_sorted_matching_branches(base)
+        .into_iter()
+        .map(|b| b.full())
+        .collect();
+    // A lone part has nothing to navigate to.
+    if parts.len() < 2 {
+        return Ok(());
+    }
+    let branches: HashSet<String> = parts.iter().cloned().collect();
+    let prs: HashMap<String, Pr> = forge
+        .prs_for_branches(&branches)
+        .await?
+        .into_iter()
+        .map(|pr| (pr.branch.clone(), pr))
+        .collect();
+    for part in &parts {
+        if !prs.get(part).map(|pr| pr.state == "OPEN").unwrap_or(false) {
+            continue;
+        }
+        // The listing may be cached, so get the body fresh before writing over it.
+        let pr = match forge.pr_for_branch(part).await? {
+            Some(pr) => pr,
+            None => continue,
+        };
+        let body = with_section(&pr.body, &section(base, &parts, &prs, part));
+        if body != pr.body {
+            forge.update_pr(&pr, None, Some(body)).await?;
+        }
+    }
+    Ok(())
+}
+
+fn section(base: &str, parts: &[String], prs: &HashMap<String, Pr>, current: &str) -> String {
+    let mut lines = vec![
+        START_MARKER.to_string(),
+        format!("**Stack `{}`**", base),
+        String::new(),
+    ];
+    for (idx, part) in parts.iter().enumerate() {
+        let entry = match prs.get(part) {
+            Some(pr) => format!(
+                "[#{}]({}) {} ({})",
+                pr.number,
+                pr.url,
+                pr.title,
+                pr.state.to_lowercase()
+            ),
+            None => format!("`{}` (no PR yet)", part),
+        };
+        let entry = match part == current {
+            true => format!("**{}** ← this PR", entry),
+            false => entry,
+        };
+        lines.push(format!("{}. {}", idx + 1, entry));
+    }
+    lines.push(END_MARKER.to_string());
+    lines.join("\n")
+}
+
+// Swaps the old section for the new one, or adds it to the end of a body that doesn't have one.
+fn with_section(body: &str, section: &str) -> String {
+    if let Some(start) = body.find(START_MARKER) {
+        if let Some(end) = body[start..].find(END_MARKER) {
+            let end = start + end + END_MARKER.len();
+            return format!("{}{}{}", &body[..start], section, &body[end..]);
+        }
+    }
+    match body.trim_end() {
+        "" => section.to_string(),
+        body => format!("{}\n\n{}", body, section),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_section, END_MARKER, START_MARKER};
+
+    fn section(text: &str) -> String {
+        format!("{}\n{}\n{}", START_MARKER, text, END_MARKER)
+    }
+
+    #[test]
+    fn adds_the_section_after_the_body() {
+        assert_eq!(
+            with_section("Fixes the thing.\n\n", &section("new")),
+            format!("Fixes the thing.\n\n{}", section("new"))
+        );
+    }
+
+    #[test]
+    fn an_empty_body_is_just_the_section() {
+        assert_eq!(with_section("  \n", &section("new")), section("new"));
+    }
+
+    #[test]
+    fn replaces_only_the_old_section() {
+        let body = format!("Before\n\n{}\n\nAfter", section("old"));
+        assert_eq!(
+            with_section(&body, &section("new")),
+            format!("Before\n\n{}\n\nAfter", section("new"))
+        );
+    }
+
+    #[test]
+    fn an_unterminated_section_is_left_alone() {
+        let body = format!("Before\n{}\nold", START_MARKER);
+        assert_eq!(
+            with_section(&body, &section("new")),
+            format!("{}\n\n{}", body, section("new"))
+        );
+    }
+}
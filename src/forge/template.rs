@@ -0,0 +1,262 @@
+use std::{
+    fs,
+    io::stdin,
+    path::{Path, PathBuf},
+};
+
+use crate::config::get_repo_root_path;
+
+// Single templates, as github and gitlab look for them.
+const TEMPLATE_FILES: &[&str] = &[
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+];
+
+// Directories of named templates to choose from.
+const TEMPLATE_DIRS: &[&str] = &[
+    ".github/PULL_REQUEST_TEMPLATE",
+    "PULL_REQUEST_TEMPLATE",
+    "docs/PULL_REQUEST_TEMPLATE",
+    ".gitlab/merge_request_templates",
+];
+
+// Headings whose section gets the commit log.
+const SUMMARY_HEADINGS: &[&str] = &["summary", "description", "what", "changes"];
+
+// Everything from this line down is gg's own and is cut before the PR is opened or updated,
+// so the PR body itself is never touched.
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+// The note at the end of the editor buffer. Template comments are only dropped from new PRs,
+// an existing body is sent back as it was written.
+pub(crate) fn instructions(from_template: bool) -> String {
+    let mut note = format!(
+        "{}\n# Everything from the line above down is dropped. The first line is the PR title\n\
+         # and the rest is its body.",
+        SCISSORS
+    );
+    if from_template {
+        note.push_str(
+            "\n# Lines starting with '# ' are dropped too, markdown headings (## and deeper) are kept.",
+        );
+    }
+    note
+}
+
+// Drops the note gg added to the buffer, and nothing else.
+pub(crate) fn cut_instructions(buffer: &str) -> String {
+    buffer
+        .lines()
+        .take_while(|l| l.trim_end() != SCISSORS)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The repo's PR template. `choice` is what came with --template: nothing gives the default
+// template, a bare --template asks which one, and a name picks it out of the template
+// directory.
+pub(crate) fn repo_template(choice: Option<Option<String>>) -> anyhow::Result<Option<String>> {
+    let root = PathBuf::from(get_repo_root_path());
+    let named = named_templates(&root);
+    let path = match choice {
+        None => TEMPLATE_FILES
+            .iter()
+            .map(|f| root.join(f))
+            .find(|p| p.is_file())
+            .or_else(|| match named.len() {
+                1 => Some(named[0].1.clone()),
+                _ => None,
+            }),
+        Some(None) => pick(&named),
+        Some(Some(name)) => Some(find_named(&named, &name)?),
+    };
+    Ok(path.map(|p| fs::read_to_string(p).expect("reading PR template")))
+}
+
+fn find_named(named: &[(String, PathBuf)], name: &str) -> anyhow::Result<PathBuf> {
+    if let Some((_, path)) = named.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Ok(path.clone());
+    }
+    match named.is_empty() {
+        true => anyhow::bail!("no template {}, the repo has no named PR templates", name),
+        false => {
+            let names: Vec<&str> = named.iter().map(|(n, _)| n.as_str()).collect();
+            anyhow::bail!("no template {}, there's {}", name, names.join(", "))
+        }
+    }
+}
+
+fn named_templates(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut templates = vec![];
+    for dir in TEMPLATE_DIRS {
+        let entries = match fs::read_dir(root.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().map(|e| e == "md").unwrap_or(false) {
+                let name = path.file_stem().unwrap().to_string_lossy().to_string();
+                templates.push((name, path));
+            }
+        }
+    }
+    templates.sort();
+    templates
+}
+
+fn pick(named: &[(String, PathBuf)]) -> Option<PathBuf> {
+    if named.is_empty() {
+        println!("There are no PR templates to pick from");
+        return None;
+    }
+    for (idx, (name, _)) in named.iter().enumerate() {
+        println!("{}: {}", idx + 1, name);
+    }
+    // Asks again until it gets one of the numbers, or a name.
+    loop {
+        println!("Which template [1-{}]: ", named.len());
+        let mut line = String::new();
+        // Nothing more to read, so nothing is going to be picked.
+        if stdin().read_line(&mut line).unwrap() == 0 {
+            return None;
+        }
+        let line = line.trim();
+        let picked = match line.parse::<usize>() {
+            Ok(idx) => idx
+                .checked_sub(1)
+                .and_then(|i| named.get(i))
+                .map(|(_, p)| p.clone()),
+            Err(_) => find_named(named, line).ok(),
+        };
+        if picked.is_some() {
+            return picked;
+        }
+        println!("{} isn't one of the templates", line);
+    }
+}
+
+// Puts the commit log under the template's summary heading, or above the template if it
+// doesn't have one.
+pub(crate) fn fill(template: &str, summary: &str) -> String {
+    let summary = summary.trim();
+    if summary.is_empty() {
+        return template.to_string();
+    }
+    let mut lines: Vec<&str> = template.lines().collect();
+    let heading = lines.iter().position(|l| {
+        let text = l.trim_start_matches('#');
+        text.len() < l.len() && SUMMARY_HEADINGS.contains(&text.trim().to_lowercase().as_str())
+    });
+    match heading {
+        Some(idx) => {
+            lines.insert(idx + 1, summary);
+            lines.join("\n")
+        }
+        None => format!("{}\n\n{}", summary, template),
+    }
+}
+
+// Drops the template's instruction lines from the edited buffer. Only a lone `#` or one followed by a
+// space counts, so markdown headings from the template survive, and code blocks are left
+// alone since a `# ` line there is a shell comment.
+pub(crate) fn strip_instructions(buffer: &str) -> String {
+    let mut fence: Option<&str> = None;
+    buffer
+        .lines()
+        .filter(|l| {
+            let trimmed = l.trim_start();
+            for marker in ["```", "~~~"] {
+                if trimmed.starts_with(marker) {
+                    match fence {
+                        None => fence = Some(marker),
+                        Some(open) if open == marker => fence = None,
+                        Some(_) => {}
+                    }
+                    return true;
+                }
+            }
+            fence.is_some() || !(l.trim_end() == "#" || l.starts_with("# "))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cut_instructions, fill, find_named, instructions, strip_instructions};
+    use std::path::PathBuf;
+
+    #[test]
+    fn fills_the_summary_section() {
+        let template = "## Summary\n\n## Testing\n";
+        assert_eq!(
+            fill(template, "Fix the thing\n"),
+            "## Summary\nFix the thing\n\n## Testing"
+        );
+    }
+
+    #[test]
+    fn matches_summary_headings_loosely() {
+        assert_eq!(fill("### What \n", "log"), "### What \nlog");
+        assert_eq!(fill("# DESCRIPTION", "log"), "# DESCRIPTION\nlog");
+    }
+
+    #[test]
+    fn prepends_without_a_summary_heading() {
+        assert_eq!(fill("## Checklist\n", "log"), "log\n\n## Checklist\n");
+        assert_eq!(fill("Summary\n", "log"), "log\n\nSummary\n");
+    }
+
+    #[test]
+    fn leaves_the_template_without_a_log() {
+        assert_eq!(fill("## Summary\n", " \n"), "## Summary\n");
+    }
+
+    #[test]
+    fn strips_only_instruction_lines() {
+        let buffer = "Title\n## Summary\n# an instruction\n#\n#hashtag\nbody";
+        assert_eq!(
+            strip_instructions(buffer),
+            "Title\n## Summary\n#hashtag\nbody"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_in_code_blocks() {
+        let buffer = "Title\n# an instruction\n```sh\n# install it\nmake\n```\n# another";
+        assert_eq!(
+            strip_instructions(buffer),
+            "Title\n```sh\n# install it\nmake\n```"
+        );
+        let tildes = "~~~\n# kept\n```\n# still kept\n~~~\n# dropped";
+        assert_eq!(
+            strip_instructions(tildes),
+            "~~~\n# kept\n```\n# still kept\n~~~"
+        );
+    }
+
+    #[test]
+    fn names_the_templates_there_are() {
+        let named = vec![
+            ("bugfix".to_string(), PathBuf::from("bugfix.md")),
+            ("feature".to_string(), PathBuf::from("feature.md")),
+        ];
+        assert_eq!(
+            find_named(&named, "Feature").unwrap(),
+            PathBuf::from("feature.md")
+        );
+        let err = find_named(&named, "docs").unwrap_err().to_string();
+        assert_eq!(err, "no template docs, there's bugfix, feature");
+    }
+
+    #[test]
+    fn cuts_only_from_the_scissors_line() {
+        let buffer = format!("Title\n# An H1\nbody\n\n{}", instructions(true));
+        assert_eq!(cut_instructions(&buffer), "Title\n# An H1\nbody\n");
+        assert_eq!(cut_instructions("Title\n# An H1"), "Title\n# An H1");
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::from_utf8,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    color,
+    git::{self, ParsedBranch},
+};
+
+// Gg-Id trailers are a stable identity for a commit, the same idea as gerrit's Change-Id.
+// Cherry-pick rebases produce new SHAs, but the trailer is carried along in the message.
+pub(crate) const TRAILER: &str = "Gg-Id";
+
+const HOOK_MARKER: &str = "# gg commit-msg hook";
+
+// Set while amending so the rewritten commit keeps its id even if the message is replaced.
+const ID_ENV: &str = "GG_ID";
+
+// Installs a commit-msg hook that calls back into gg to add the trailer.
+pub(crate) fn install_commit_msg_hook() {
+    let hook_path = hooks_dir().join("commit-msg");
+    if let Ok(existing) = fs::read_to_string(&hook_path) {
+        if !existing.contains(HOOK_MARKER) {
+            println!(
+                "{} {:?} already exists, add '{} commit-msg \"$1\"' to it to get {} trailers",
+                color::yellow("Not installing commit-msg hook:"),
+                hook_path,
+                current_exe(),
+                TRAILER,
+            );
+            return;
+        }
+    }
+    fs::create_dir_all(hooks_dir()).expect("could not create hooks dir");
+    let script = format!(
+        "#!/bin/sh\n{}\nexec \"{}\" commit-msg \"$1\"\n",
+        HOOK_MARKER,
+        current_exe()
+    );
+    fs::write(&hook_path, script).expect("could not write commit-msg hook");
+    fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))
+        .expect("could not make commit-msg hook executable");
+    println!("Installed commit-msg hook at {:?}", hook_path);
+}
+
+fn hooks_dir() -> PathBuf {
+    let out = Command::new("git")
+        .arg("rev-parse")
+        .arg("--git-path")
+        .arg("hooks")
+        .output()
+        .expect("failed to find hooks dir");
+    let x: &[_] = &[' ', '\t', '\n', '\r'];
+    PathBuf::from(from_utf8(&out.stdout).expect("msg").trim_end_matches(x))
+}
+
+fn current_exe() -> String {
+    std::env::current_exe()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "gg".to_string())
+}
+
+// Called by the commit-msg hook with the path of the message being committed.
+pub(crate) fn add_to_message_file(path: &str) {
+    if git::current_parsed_branch().prefix.is_none() {
+        return;
+    }
+    let msg = fs::read_to_string(path).expect("could not read commit message");
+    if is_empty_message(&msg) || parse_ids(&msg).next().is_some() {
+        return;
+    }
+    assert!(Command::new("git")
+        .arg("interpret-trailers")
+        .arg("--in-place")
+        .arg("--if-exists")
+        .arg("doNothing")
+        .arg("--trailer")
+        .arg(format!("{}: {}", TRAILER, id_for_new_commit(&msg)))
+        .arg(path)
+        .status()
+        .expect("failed to add trailer")
+        .success());
+}
+
+// Used by `gg commit`/`gg amend`, which shouldn't depend on the hook being installed.
+pub(crate) fn ensure_head_has_id() {
+    if git::current_parsed_branch().prefix.is_none() || id_for_commit("HEAD").is_some() {
+        return;
+    }
+    let msg = message_for_commit("HEAD");
+    let with_trailer = run_with_stdin(
+        Command::new("git")
+            .arg("interpret-trailers")
+            .arg("--trailer")
+            .arg(format!("{}: {}", TRAILER, id_for_new_commit(&msg))),
+        &msg,
+    );
+    assert!(Command::new("git")
+        .arg("-c")
+        .arg(git::NO_HOOKS)
+        .arg("commit")
+        .arg("--amend")
+        .arg("--quiet")
+        .arg("--cleanup=verbatim")
+        .arg("-m")
+        .arg(with_trailer)
+        .status()
+        .expect("failed to amend commit")
+        .success());
+}
+
+fn is_empty_message(msg: &str) -> bool {
+    msg.lines()
+        .all(|l| l.trim().is_empty() || l.starts_with('#'))
+}
+
+// Makes the amend of HEAD that's about to happen keep HEAD's id.
+pub(crate) fn keep_id_for_amend() {
+    if let Some(id) = id_for_commit("HEAD") {
+        std::env::set_var(ID_ENV, id);
+    }
+}
+
+fn id_for_new_commit(msg: &str) -> String {
+    std::env::var(ID_ENV).unwrap_or_else(|_| new_id(msg))
+}
+
+fn new_id(msg: &str) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_nanos();
+    let seed = format!("{} {} {}", now, std::process::id(), msg);
+    let hash = run_with_stdin(Command::new("git").arg("hash-object").arg("--stdin"), &seed);
+    format!("I{}", hash)
+}
+
+fn run_with_stdin(c: &mut Command, input: &str) -> String {
+    let mut child = c
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn git");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .expect("failed to write to git");
+    let out = child.wait_with_output().expect("failed to run git");
+    let x: &[_] = &[' ', '\t', '\n', '\r'];
+    from_utf8(&out.stdout)
+        .expect("msg")
+        .trim_end_matches(x)
+        .to_string()
+}
+
+fn message_for_commit(rev: &str) -> String {
+    let out = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%B")
+        .arg(rev)
+        .output()
+        .expect("failed to read commit message");
+    from_utf8(&out.stdout).expect("msg").to_string()
+}
+
+pub(crate) fn id_for_commit(rev: &str) -> Option<String> {
+    parse_ids(&message_for_commit(rev)).last()
+}
+
+// Squash merges fold every commit message into the body, so any line (not just the
+// trailer block) counts.
+fn parse_ids(msg: &str) -> impl Iterator<Item = String> + '_ {
+    msg.lines().filter_map(|l| {
+        l.trim()
+            .strip_prefix(TRAILER)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    })
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct IdentifiedCommit {
+    pub(crate) sha: String,
+    pub(crate) id: Option<String>,
+}
+
+// Commits in start..end, oldest first.
+pub(crate) fn commits_in_range(start: &str, end: &str) -> Vec<IdentifiedCommit> {
+    let out = Command::new("git")
+        .arg("log")
+        .arg("--reverse")
+        .arg("--format=%H%x00%B%x1e")
+        .arg(format!("{}..{}", start, end))
+        .output()
+        .expect("failed to list commits");
+    from_utf8(&out.stdout)
+        .expect("msg")
+        .split('\x1e')
+        .filter_map(|entry| {
+            let (sha, msg) = entry.trim_start().split_once('\x00')?;
+            Some(IdentifiedCommit {
+                sha: sha.to_string(),
+                id: parse_ids(msg).last(),
+            })
+        })
+        .collect()
+}
+
+// All the ids that appear in start..end, including ones folded into squash merges.
+pub(crate) fn ids_in_range(start: &str, end: &str) -> HashSet<String> {
+    let out = Command::new("git")
+        .arg("log")
+        .arg("--format=%B")
+        .arg(format!("{}..{}", start, end))
+        .output()
+        .expect("failed to list commits");
+    parse_ids(from_utf8(&out.stdout).expect("msg")).collect()
+}
+
+// A branch is landed when every commit on it carries an id that already made it to `main`.
+pub(crate) fn is_landed(branch: &ParsedBranch, main: &str) -> bool {
+    let commits = commits_in_range(&branch.start(), &branch.full());
+    if commits.is_empty() || commits.iter().any(|c| c.id.is_none()) {
+        return false;
+    }
+    let landed = ids_in_range(&branch.start(), main);
+    commits
+        .iter()
+        .all(|c| landed.contains(c.id.as_ref().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_ids;
+
+    #[test]
+    fn reads_the_trailer() {
+        let msg = "Fix the thing\n\nBody text.\n\nGg-Id: abc123\n";
+        assert_eq!(parse_ids(msg).collect::<Vec<_>>(), vec!["abc123"]);
+    }
+
+    #[test]
+    fn finds_every_id_in_a_squashed_message() {
+        let msg = "Part one (#1)\n\n* First\n  Gg-Id: one\n\n* Second\n  Gg-Id: two\n";
+        assert_eq!(parse_ids(msg).collect::<Vec<_>>(), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn skips_empty_and_lookalike_lines() {
+        let msg = "Gg-Id:\nGg-Idx: nope\nmentions Gg-Id: inline\n";
+        assert_eq!(parse_ids(msg).count(), 0);
+    }
+}
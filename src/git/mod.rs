@@ -1,10 +1,25 @@
 use std::str::from_utf8;
 
-use std::process::Command;
+use std::{collections::HashMap, fs, path::PathBuf, process::Command};
 
 use crate::{color, config};
 use config::get_saved_config;
 
+// Where git keeps `name` for this checkout. In a worktree or submodule `.git` is a file, so
+// state can't just go under `<root>/.git`.
+pub(crate) fn git_path(name: &str) -> PathBuf {
+    let out = Command::new("git")
+        .arg("rev-parse")
+        .arg("--git-path")
+        .arg(name)
+        .output()
+        .expect("failed to find git dir");
+    if !out.status.success() {
+        panic!("error finding {} in the git dir", name);
+    }
+    PathBuf::from(from_utf8(&out.stdout).expect("msg").trim_end())
+}
+
 pub(crate) fn new(branch: &str) {
     Command::new("git")
         .arg("checkout")
@@ -160,6 +175,25 @@ pub(crate) fn fetch_branch(br: &str) {
         .expect("failed to fetch main branch");
 }
 
+// Runs `git commit` (or `git commit --amend`) interactively, returning whether it succeeded.
+pub(crate) fn commit(message: Option<String>, all: bool, amend: bool, no_edit: bool) -> bool {
+    let mut c = Command::new("git");
+    c.arg("commit");
+    if amend {
+        c.arg("--amend");
+    }
+    if all {
+        c.arg("--all");
+    }
+    if no_edit {
+        c.arg("--no-edit");
+    }
+    if let Some(message) = message {
+        c.arg("-m").arg(message);
+    }
+    c.status().expect("failed to commit").success()
+}
+
 pub(crate) fn fixup_main() {
     let current_branch = current_parsed_branch();
     Command::new("git")
@@ -186,19 +220,178 @@ pub(crate) fn rebase(interactive: bool) {
         .success(),);
 }
 
-pub(crate) fn checkout_main() {
-    let cfg = config::get_saved_config();
-    checkout(&format!("origin/{}", cfg.repo_main_branch));
+pub(crate) fn checkout(branch: &String) -> anyhow::Result<()> {
+    let out = Command::new("git")
+        .arg("checkout")
+        .arg(branch)
+        .output()
+        .expect("failed to checkout main");
+    if !out.status.success() {
+        anyhow::bail!(
+            "failed to checkout {}: {}",
+            branch,
+            from_utf8(&out.stderr).expect("msg").trim()
+        );
+    }
+    Ok(())
+}
+
+// Checkout for commands the user runs to move around: any uncommitted changes are stashed
+// for the branch we're leaving, and changes stashed earlier for the target are reapplied.
+pub(crate) fn switch_to(branch: &String) -> anyhow::Result<()> {
+    let stashed = stash_if_dirty();
+    if let Err(e) = checkout(branch) {
+        if stashed {
+            restore_autostash();
+        }
+        return Err(e);
+    }
+    restore_autostash();
+    Ok(())
+}
+
+// Checkout for when the branch we're on is about to be deleted (or left for a new one), so
+// there's nobody to come back for its stash: uncommitted changes come along to `branch`.
+pub(crate) fn move_to(branch: &String) -> anyhow::Result<()> {
+    let orig = current_branch();
+    let stashed = stash_if_dirty();
+    let res = checkout(branch);
+    if stashed {
+        pop_autostash(&orig);
+    }
+    res
+}
+
+// Runs something that moves HEAD (or resets the current branch), keeping any uncommitted
+// changes out of its way and reapplying them on the original branch afterwards. The changes
+// are reapplied whether or not `f` goes through.
+pub(crate) fn with_autostash<R, F: FnOnce() -> anyhow::Result<R>>(f: F) -> anyhow::Result<R> {
+    let orig = current_branch();
+    let stashed = stash_if_dirty();
+    if stashed {
+        // A rebase in `f` can stop on a conflict, this is how its --continue/--abort find
+        // their way back.
+        fs::write(autostash_origin_path(), &orig).expect("saving autostash branch");
+    }
+    let res = f();
+    if stashed {
+        fs::remove_file(autostash_origin_path()).ok();
+        if current_branch() != orig {
+            checkout(&orig)?;
+        }
+        restore_autostash();
+    }
+    res
+}
+
+fn autostash_origin_path() -> PathBuf {
+    git_path("GG_AUTOSTASH_BRANCH")
+}
+
+// For once a rebase that stopped on a conflict is continued or aborted: goes back to the
+// branch with_autostash started on and reapplies its changes, as it would have had the
+// rebase gone through.
+pub(crate) fn finish_autostash() -> anyhow::Result<()> {
+    let orig = match fs::read_to_string(autostash_origin_path()) {
+        Ok(orig) => orig,
+        Err(_) => {
+            restore_autostash();
+            return Ok(());
+        }
+    };
+    fs::remove_file(autostash_origin_path()).ok();
+    if current_branch() != orig {
+        checkout(&orig)?;
+    }
+    restore_autostash();
+    Ok(())
+}
+
+fn autostash_name(branch: &str) -> String {
+    format!("gg-autostash/{}", branch)
+}
+
+fn is_dirty() -> bool {
+    let out = Command::new("git")
+        .arg("status")
+        .arg("--porcelain")
+        .arg("--untracked-files=no")
+        .output()
+        .expect("failed to get status");
+    !out.stdout.is_empty()
 }
 
-pub(crate) fn checkout(branch: &String) {
+pub(crate) fn stash_if_dirty() -> bool {
+    if !is_dirty() {
+        return false;
+    }
+    let name = autostash_name(&current_branch());
     assert!(Command::new("git")
-        .arg("checkout")
-        .arg(branch)
+        .arg("stash")
+        .arg("push")
+        .arg("-m")
+        .arg(&name)
         .output()
-        .expect("failed to checkout main")
+        .expect("failed to stash changes")
         .status
         .success());
+    println!("Stashed uncommitted changes as {}", color::bold(name));
+    true
+}
+
+fn find_autostash(branch: &str) -> Option<String> {
+    let out = Command::new("git")
+        .arg("stash")
+        .arg("list")
+        .arg("--format=%gd%x00%gs")
+        .output()
+        .expect("failed to list stashes");
+    let suffix = format!(": {}", autostash_name(branch));
+    from_utf8(&out.stdout)
+        .expect("msg")
+        .lines()
+        .filter_map(|l| l.split_once('\x00'))
+        .find(|(_, subject)| subject.ends_with(suffix.as_str()))
+        .map(|(stash_ref, _)| stash_ref.to_string())
+}
+
+// Reapplies the changes gg stashed for the current branch, if there are any.
+pub(crate) fn restore_autostash() {
+    pop_autostash(&current_branch());
+}
+
+// Reapplies the changes gg stashed for `stashed_for` onto whatever is checked out.
+fn pop_autostash(stashed_for: &str) {
+    let branch = current_branch();
+    let stash_ref = match find_autostash(stashed_for) {
+        Some(r) => r,
+        None => return,
+    };
+    let out = Command::new("git")
+        .arg("stash")
+        .arg("pop")
+        .arg(&stash_ref)
+        .output()
+        .expect("failed to pop stash");
+    if out.status.success() {
+        println!("Reapplied uncommitted changes on {}", branch);
+        return;
+    }
+    println!(
+        "{}",
+        color::bold(color::red(format!(
+            "Could not cleanly reapply your uncommitted changes on {}",
+            branch
+        )))
+    );
+    println!("{}", from_utf8(&out.stdout).expect("msg"));
+    println!("{}", from_utf8(&out.stderr).expect("msg"));
+    println!(
+        "Your changes are still saved in {} ({}). Resolve the conflicts above, then run 'git stash drop {}'",
+        stash_ref,
+        autostash_name(stashed_for),
+        stash_ref
+    );
 }
 
 pub(crate) fn reset(branch: String, hard: bool) {
@@ -267,7 +460,6 @@ pub(crate) enum CheckoutDir {
     Prev,
     Start,
     Part(f32),
-    Unknown,
 }
 
 pub(crate) fn get_branch_for_dir(dir: CheckoutDir) -> Option<String> {
@@ -290,7 +482,7 @@ pub(crate) fn get_branch_for_dir(dir: CheckoutDir) -> Option<String> {
         }
         CheckoutDir::Prev => {
             if location < 1 {
-                None
+                stack_dependency(&parsed_branch.base)
             } else {
                 Some(branches[location - 1].full())
             }
@@ -303,7 +495,6 @@ pub(crate) fn get_branch_for_dir(dir: CheckoutDir) -> Option<String> {
                 .find(|x| x.partx100.is_some() && x.partx100.unwrap() == partx100)
                 .map(|br| br.full())
         }
-        CheckoutDir::Unknown => None,
     }
 }
 
@@ -315,13 +506,72 @@ pub(crate) fn get_children_branches(branch: &ParsedBranch) -> Vec<ParsedBranch>
         .collect()
 }
 
+// The branch (from another stack) that the first part of this stack builds on, if it's
+// still around.
+pub(crate) fn stack_dependency(base: &str) -> Option<String> {
+    let dep = get_saved_config().stack_deps.get(base)?.clone();
+    if all_branches().contains(&dep) {
+        Some(dep)
+    } else {
+        None
+    }
+}
+
+// The stacks from `base` round to itself, if making it build on `on` would close a loop.
+pub(crate) fn dependency_cycle(base: &str, on: &str) -> Option<Vec<String>> {
+    find_cycle(&get_saved_config().stack_deps, base, on, |b| {
+        parse_branch(b.to_string()).base
+    })
+}
+
+// Follows the stacks `on` builds on, one dependency at a time, looking for `base`.
+fn find_cycle(
+    deps: &HashMap<String, String>,
+    base: &str,
+    on: &str,
+    base_of: impl Fn(&str) -> String,
+) -> Option<Vec<String>> {
+    let mut chain = vec![base.to_string()];
+    let mut cur = base_of(on);
+    loop {
+        if cur == base {
+            chain.push(cur);
+            return Some(chain);
+        }
+        // A loop that doesn't go through `base` isn't this one's to report.
+        if chain.contains(&cur) {
+            return None;
+        }
+        chain.push(cur.clone());
+        cur = base_of(deps.get(&cur)?);
+    }
+}
+
+// The first parts of the stacks that build on `branch`.
+pub(crate) fn get_dependent_stacks(branch: &ParsedBranch) -> Vec<ParsedBranch> {
+    let full = branch.full();
+    get_saved_config()
+        .stack_deps
+        .into_iter()
+        .filter(|(_, dep)| *dep == full)
+        .filter_map(|(base, _)| get_sorted_matching_branches(&base).into_iter().next())
+        .collect()
+}
+
+pub(crate) fn is_first_part(branch: &ParsedBranch) -> bool {
+    get_sorted_matching_branches(&branch.base)
+        .first()
+        .map(|b| b.partx100 == branch.partx100)
+        .unwrap_or(false)
+}
+
 pub(crate) fn get_sorted_matching_branches(base: &str) -> Vec<ParsedBranch> {
     let mut v: Vec<ParsedBranch> = all_managed_branches()
         .into_iter()
-        .map(|b| parse_branch(b))
+        .map(parse_branch)
         .filter(|b| b.base == base)
         .collect();
-    v.sort_by(|a, b| a.partx100.cmp(&b.partx100));
+    v.sort_by_key(|a| a.partx100);
     v
 }
 
@@ -383,7 +633,7 @@ impl ParsedBranch {
     }
 }
 
-pub(crate) fn is_start_branch(branch: &String) -> bool {
+pub(crate) fn is_start_branch(branch: &str) -> bool {
     let cfg = get_saved_config();
     branch.starts_with(format!("{}{}{}", cfg.branch_prefix, cfg.branch_split, "starts").as_str())
 }
@@ -430,12 +680,22 @@ fn partfloat_to_partx100(part: f32) -> u32 {
     (part * 100.0) as u32
 }
 
+// Hooks break the cherry-pick rebase and shouldn't re-run on gg's own amends, so they're
+// switched off for just those git invocations. This never touches the user's git config.
+pub(crate) const NO_HOOKS: &str = "core.hooksPath=/dev/null";
+
 pub(crate) fn cherry_pick(start_ref: String, end_ref: String, strategy: Option<String>) {
+    cherry_pick_commits(vec![format!("{}..{}", start_ref, end_ref)], strategy);
+}
+
+pub(crate) fn cherry_pick_commits(commits: Vec<String>, strategy: Option<String>) {
     let mut c = Command::new("git");
 
-    c.arg("cherry-pick")
+    c.arg("-c")
+        .arg(NO_HOOKS)
+        .arg("cherry-pick")
         .arg("-v")
-        .arg(format!("{}..{}", start_ref, end_ref));
+        .args(commits);
 
     if let Some(strategy) = strategy {
         c.arg("--strategy-option").arg(strategy);
@@ -455,6 +715,8 @@ pub(crate) fn cherry_abort() {
 
 pub(crate) fn cherry_continue() {
     assert!(Command::new("git")
+        .arg("-c")
+        .arg(NO_HOOKS)
         .arg("cherry-pick")
         .arg("--continue")
         .status()
@@ -476,6 +738,36 @@ pub(crate) fn get_commit_hash(branch: String) -> String {
     result.to_string()
 }
 
+// The subject line of each commit in start..end.
+pub(crate) fn commit_subjects(start_ref: &str, end_ref: &str) -> Vec<String> {
+    let out = Command::new("git")
+        .arg("log")
+        .arg("--format=%s")
+        .arg(format!("{}..{}", start_ref, end_ref))
+        .output()
+        .expect("failed to list commits");
+    from_utf8(&out.stdout)
+        .expect("msg")
+        .lines()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+// The paths of the files changed in start..end.
+pub(crate) fn changed_files(start_ref: &str, end_ref: &str) -> Vec<String> {
+    let out = Command::new("git")
+        .arg("diff")
+        .arg("--name-only")
+        .arg(format!("{}..{}", start_ref, end_ref))
+        .output()
+        .expect("failed to list changed files");
+    from_utf8(&out.stdout)
+        .expect("msg")
+        .lines()
+        .map(|l| l.to_string())
+        .collect()
+}
+
 pub(crate) fn assert_branch_exists(branch: String) {
     let _ = get_commit_hash(branch);
 }
@@ -536,3 +828,47 @@ fn get_create_timestamp(br: &str) -> i64 {
     println!("{}", result);
     result.parse::<i64>().expect("failed to parse timestamp")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::find_cycle;
+    use std::collections::HashMap;
+
+    // Branches here are "<stack>/<part>".
+    fn base_of(branch: &str) -> String {
+        branch.split('/').next().unwrap().to_string()
+    }
+
+    fn deps(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(base, dep)| (base.to_string(), dep.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_a_loop_back_to_the_stack() {
+        let deps = deps(&[("b", "a/1")]);
+        assert_eq!(
+            find_cycle(&deps, "a", "b/2", base_of),
+            Some(vec!["a".to_string(), "b".to_string(), "a".to_string()])
+        );
+    }
+
+    #[test]
+    fn a_stack_cant_build_on_itself() {
+        assert!(find_cycle(&HashMap::new(), "a", "a/1", base_of).is_some());
+    }
+
+    #[test]
+    fn allows_a_chain_without_a_loop() {
+        let deps = deps(&[("b", "c/1")]);
+        assert_eq!(find_cycle(&deps, "a", "b/1", base_of), None);
+    }
+
+    #[test]
+    fn stops_at_a_loop_elsewhere() {
+        let deps = deps(&[("b", "c/1"), ("c", "b/1")]);
+        assert_eq!(find_cycle(&deps, "a", "b/1", base_of), None);
+    }
+}
@@ -1,140 +1,142 @@
-use std::{fs, process::Command, str::from_utf8};
+use std::collections::HashSet;
 
-use crate::git::{assert_branch_exists, checkout, cherry_abort, cherry_continue, cherry_pick, current_branch, current_parsed_branch, delete_branch_all, delete_branch_local, force_branch_to_be, get_branch_for_dir, get_children_branches, get_commit_hash, new, parse_branch, reset, ParsedBranch};
+use crate::gg_id;
+use crate::hooks::{self, Hook, HookPayload};
+use crate::git::{assert_branch_exists, checkout, cherry_abort, cherry_continue, cherry_pick, cherry_pick_commits, current_branch, current_parsed_branch, delete_branch_all, delete_branch_local, force_branch_to_be, get_branch_for_dir, get_children_branches, get_commit_hash, get_dependent_stacks, new, parse_branch, reset, with_autostash, ParsedBranch};
 
-pub(crate) fn rebase_all_children(strategy: Option<String>) {
-    let cur = current_parsed_branch();
+pub(crate) fn rebase_all_children(strategy: Option<String>) -> anyhow::Result<()> {
+    rebase_children_of(current_parsed_branch(), strategy, &mut HashSet::new())
+}
+
+// `visited` has the stacks moved so far, so a loop in the stack dependencies (from a config
+// edited by hand, say) can't send this round and round.
+fn rebase_children_of(cur: ParsedBranch, strategy: Option<String>, visited: &mut HashSet<String>) -> anyhow::Result<()> {
+    visited.insert(cur.base.clone());
     let children = get_children_branches(&cur);
 
+    let mut rebased = vec![cur];
     for child in children {
-        checkout(&child.full());
-        start_rebase(None, strategy.clone());
+        checkout(&child.full())?;
+        start_rebase(None, strategy.clone())?;
+        rebased.push(child);
+    }
+
+    // Follow stacks that build on any of the parts we just moved.
+    for parent in rebased {
+        for dependent in get_dependent_stacks(&parent) {
+            if !visited.insert(dependent.base.clone()) {
+                println!("Skipping {}, it was already restacked", dependent.base);
+                continue;
+            }
+            checkout(&dependent.full())?;
+            start_rebase(None, strategy.clone())?;
+            rebase_children_of(dependent, strategy.clone(), visited)?;
+        }
+    }
+    Ok(())
+}
+
+// Moves every part above the current one (and any stacks built on them) onto the current
+// tip, then comes back to the current part.
+pub(crate) fn restack_children(strategy: Option<String>) -> anyhow::Result<()> {
+    let cur = current_parsed_branch();
+    if get_children_branches(&cur).is_empty() && get_dependent_stacks(&cur).is_empty() {
+        return Ok(());
     }
+    with_autostash(|| {
+        rebase_all_children(strategy)?;
+        checkout(&cur.full())
+    })
 }
 
-pub(crate) fn start_rebase(onto: Option<String>, strategy: Option<String>) {
-    setup_hooks_path();
+pub(crate) fn start_rebase(onto: Option<String>, strategy: Option<String>) -> anyhow::Result<()> {
     let cur = TmpBranchWrapper::new(current_parsed_branch());
     let onto = onto.unwrap_or_else(|| {
         get_branch_for_dir(crate::git::CheckoutDir::Prev).expect("No previous branch to rebase onto")
     });
     assert_branch_exists(onto.clone());
-    rebase_onto(cur, onto, strategy);
+    rebase_onto(cur, onto, strategy)
 }
 
-pub(crate) fn abort_rebase() {
+pub(crate) fn abort_rebase() -> anyhow::Result<()> {
     let br = TmpBranchWrapper::new_from_tmp_branch(current_branch());
 
     cherry_abort();
 
-    checkout(&br.inner.full());
+    checkout(&br.inner.full())?;
 
     delete_branch_all(br.tmp_branch_name());
     delete_branch_all(br.tmp_start_branch_name());
+    Ok(())
 }
 
-pub(crate) fn continue_rebase() {
+pub(crate) fn continue_rebase() -> anyhow::Result<()> {
     let br = TmpBranchWrapper::new_from_tmp_branch(current_branch());
 
     cherry_continue();
 
-    finish_rebase(br);
+    finish_rebase(br)
 }
 
-fn rebase_onto(branch_to_rebase: TmpBranchWrapper, onto: String, strategy: Option<String>) {
+fn rebase_onto(branch_to_rebase: TmpBranchWrapper, onto: String, strategy: Option<String>) -> anyhow::Result<()> {
     if branches_are_equivalent(branch_to_rebase.inner.start(), branch_to_rebase.inner.full()) {
         println!("There are no commits to rebase, fast forwarding the branches");
-        force_branch_to_be(&branch_to_rebase.inner.full(), &onto);
-        force_branch_to_be(&branch_to_rebase.inner.start(), &onto);
-        return;
+        force_branch_to_be(branch_to_rebase.inner.full(), &onto);
+        force_branch_to_be(branch_to_rebase.inner.start(), &onto);
+        run_post_rebase_hook(&branch_to_rebase.inner);
+        return Ok(());
     }
     // TODO CREATE WORKTREE TO ISOLATE BRANCH REBASE
     println!("Rebasing {} onto {} via cherry-picks", branch_to_rebase.inner.full(), onto);
-    checkout(&onto);
+    checkout(&onto)?;
     new(branch_to_rebase.tmp_start_branch_name().as_str());
     new(branch_to_rebase.tmp_branch_name().as_str());
     println!("Cherry-picking changes onto branch {}", onto);
-    cherry_pick(
-        branch_to_rebase.inner.start(),
-        branch_to_rebase.inner.full(),
-        strategy,
-    );
-    finish_rebase(branch_to_rebase);
+    let commits = gg_id::commits_in_range(&branch_to_rebase.inner.start(), &branch_to_rebase.inner.full());
+    let landed = gg_id::ids_in_range(&branch_to_rebase.inner.start(), &onto);
+    let (skipped, to_pick): (Vec<_>, Vec<_>) = commits
+        .into_iter()
+        .partition(|c| c.id.as_ref().map(|id| landed.contains(id)).unwrap_or(false));
+    if skipped.is_empty() {
+        cherry_pick(
+            branch_to_rebase.inner.start(),
+            branch_to_rebase.inner.full(),
+            strategy,
+        );
+    } else {
+        for commit in &skipped {
+            println!("Skipping {}, its {} is already in {}", commit.sha, gg_id::TRAILER, onto);
+        }
+        if !to_pick.is_empty() {
+            cherry_pick_commits(to_pick.into_iter().map(|c| c.sha).collect(), strategy);
+        }
+    }
+    finish_rebase(branch_to_rebase)
 }
 
-pub(crate) fn fixup_rebase() {
+pub(crate) fn fixup_rebase() -> anyhow::Result<()> {
     let br = TmpBranchWrapper::new_from_tmp_branch(current_branch());
 
-    finish_rebase(br);
+    finish_rebase(br)
 }
 
-fn finish_rebase(br: TmpBranchWrapper) {
-    checkout(&br.inner.start());
+fn finish_rebase(br: TmpBranchWrapper) -> anyhow::Result<()> {
+    checkout(&br.inner.start())?;
     reset(br.tmp_start_branch_name(), true);
 
-    checkout(&br.inner.full());
+    checkout(&br.inner.full())?;
     reset(br.tmp_branch_name(), true);
 
     delete_branch_local(&br.tmp_branch_name());
     delete_branch_local(&br.tmp_start_branch_name());
-    finish_up_hooks_path();
+    run_post_rebase_hook(&br.inner);
+    Ok(())
 }
 
-// Hooks break the rebase, so we need to delete before the rebase and set it up again after
-fn setup_hooks_path() {
-    store_hooks_path();
-    set_hooks_path(DEVNULL);
+fn run_post_rebase_hook(br: &ParsedBranch) {
+    hooks::run(Hook::PostRebase, HookPayload::for_branch(br, None)).expect("post-rebase hook");
 }
 
-fn finish_up_hooks_path() {
-    set_hooks_path(get_stored_hooks_path().as_str());
-    fs::remove_file(TMP_HOOKS_FILE).expect("Failed to remove temp hooks file");
-}
-
-fn store_hooks_path() {
-    let path = get_hooks_path();
-    fs::write(TMP_HOOKS_FILE, path).expect("Could not write hooks path to file");
-}
-
-fn get_stored_hooks_path() -> String {
-    fs::read_to_string(TMP_HOOKS_FILE).expect("No hooks file to pull hooks info")
-}
-
-fn get_hooks_path() -> String {
-    let out = Command::new("git")
-            .arg("config")
-            .arg("core.hooksPath")
-            .output()
-            .expect("failed to create branch");
-
-    let x: &[_] = &[' ', '\t', '\n', '\r', '*'];
-    from_utf8(&out.stdout)
-        .expect("msg")
-        .trim_end_matches(x)
-        .to_string()
-}
-
-fn set_hooks_path(path: &str) {
-    if path.is_empty() {
-        Command::new("git")
-            .arg("config")
-            .arg("--unset")
-            .arg("core.hooksPath")
-            .output()
-            .expect("failed to create branch");
-    } else {
-        Command::new("git")
-            .arg("config")
-            .arg("core.hooksPath")
-            .arg(path)
-            .output()
-            .expect("failed to create branch");
-    }
-}
-
-const TMP_HOOKS_FILE: &str = "/tmp/git_rebase_hooks";
-const DEVNULL: &str = "/dev/null";
-
 const TMP_PREFIX: &str  = "_tmp_-";
 
 struct TmpBranchWrapper {
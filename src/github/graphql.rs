@@ -0,0 +1,107 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::github::GithubRepo;
+use octocrab::Octocrab;
+
+#[derive(Serialize)]
+struct GraphqlRequest<'a, V: Serialize> {
+    query: &'a str,
+    variables: V,
+}
+
+#[derive(Deserialize)]
+struct GraphqlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PageInfo {
+    pub(crate) has_next_page: bool,
+    pub(crate) end_cursor: Option<String>,
+}
+
+// A `{ nodes { ... } }` list, with `pageInfo` when the query asked for it.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Connection<T> {
+    pub(crate) page_info: Option<PageInfo>,
+    #[serde(default = "Vec::new")]
+    pub(crate) nodes: Vec<Option<T>>,
+}
+
+impl<T> Connection<T> {
+    // Github returns null for nodes we can't see, those are skipped.
+    pub(crate) fn into_nodes(self) -> impl Iterator<Item = T> {
+        self.nodes.into_iter().flatten()
+    }
+
+    pub(crate) fn next_cursor(&self) -> Option<String> {
+        match &self.page_info {
+            Some(PageInfo {
+                has_next_page: true,
+                end_cursor,
+            }) => end_cursor.clone(),
+            _ => None,
+        }
+    }
+}
+
+// Posts a query to a graphql endpoint, turning graphql errors (and missing data) into an Err.
+// Gitlab's graphql api speaks the same protocol, so its backend uses this too.
+pub(crate) async fn run_query<T, V>(
+    octo: &Octocrab,
+    path: &str,
+    query: &str,
+    variables: V,
+) -> anyhow::Result<T>
+where
+    T: DeserializeOwned,
+    V: Serialize,
+{
+    let res: GraphqlResponse<T> = octo
+        .post(path, Some(&GraphqlRequest { query, variables }))
+        .await
+        .map_err(anyhow::Error::msg)?;
+    if !res.errors.is_empty() {
+        let messages: Vec<String> = res.errors.into_iter().map(|e| e.message).collect();
+        return Err(anyhow::anyhow!("graphql error: {}", messages.join(", ")));
+    }
+    res.data
+        .ok_or_else(|| anyhow::anyhow!("graphql response had no data"))
+}
+
+impl GithubRepo {
+    pub(crate) async fn graphql<T, V>(&self, query: &str, variables: V) -> anyhow::Result<T>
+    where
+        T: DeserializeOwned,
+        V: Serialize,
+    {
+        run_query(&self.graphql_octo, self.graphql_path.as_str(), query, variables).await
+    }
+
+    // Runs a mutation that takes a single `input` argument of type `input_type`, which is
+    // how all of github's mutations are shaped.
+    pub(crate) async fn mutate<V: Serialize>(
+        &self,
+        name: &str,
+        input_type: &str,
+        input: V,
+    ) -> anyhow::Result<()> {
+        let mutation = format!(
+            "mutation($input: {}!) {{ {}(input: $input) {{ clientMutationId }} }}",
+            input_type, name
+        );
+        let _res: serde_json::Value = self
+            .graphql(mutation.as_str(), serde_json::json!({ "input": input }))
+            .await?;
+        Ok(())
+    }
+}
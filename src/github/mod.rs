@@ -1,28 +1,266 @@
+pub(crate) mod graphql;
 pub mod issue;
+pub mod mutations;
 pub mod pr;
+pub mod review;
 
-use crate::config::FullConfig;
-use octocrab::Octocrab;
+use std::collections::HashSet;
+
+use crate::{
+    config::FullConfig,
+    db::Db,
+    forge::{
+        cache::current_login, split_origin, Forge, Issue, MergeOptions, Pr, PrMetadata, ReviewThread,
+    },
+};
+use async_trait::async_trait;
+use octocrab::{models::IssueState, params::pulls::MergeMethod, Octocrab};
 
 pub struct GithubRepo {
     pub org: String,
     pub repo: String,
     pub(crate) current_user: String,
     pub(crate) octo: Octocrab,
+    // Github Enterprise serves graphql outside of the REST base path, so it gets its own client.
+    pub(crate) graphql_octo: Octocrab,
+    pub(crate) graphql_path: String,
+    pub(crate) web_url: String,
 }
 
 impl GithubRepo {
-    pub async fn new(cfg: FullConfig) -> GithubRepo {
+    pub async fn new(cfg: FullConfig, db: &Db) -> anyhow::Result<GithubRepo> {
         let octo = Octocrab::builder()
-            .personal_token(cfg.github_token)
+            .personal_token(cfg.token.clone())
+            .base_uri(cfg.saved.github_api_url())
+            .expect("invalid github api url")
+            .build()
+            .unwrap();
+        let (graphql_origin, graphql_path) = split_origin(&cfg.saved.github_graphql_url());
+        let graphql_octo = Octocrab::builder()
+            .personal_token(cfg.token)
+            .base_uri(graphql_origin)
+            .expect("invalid github graphql url")
             .build()
             .unwrap();
-        let current_user = octo.current().user().await.unwrap().login;
-        GithubRepo {
-            org: cfg.saved.repo_org,
+        let current_user = current_login(
+            db,
+            &cfg.saved.host()?,
+            async {
+                let user = octo.current().user().await.map_err(anyhow::Error::msg)?;
+                Ok(user.login)
+            },
+        )
+        .await;
+        Ok(GithubRepo {
+            org: cfg.saved.repo_org.clone(),
             repo: cfg.repo_name,
             current_user,
             octo,
+            graphql_octo,
+            graphql_path,
+            web_url: cfg.saved.github_web_url(),
+        })
+    }
+}
+
+#[async_trait]
+impl Forge for GithubRepo {
+    fn issue_url(&self, number: u64) -> String {
+        format!("{}/{}/{}/issues/{}", self.web_url, self.org, self.repo, number)
+    }
+
+    async fn open_pr(
+        &self,
+        full_branch: String,
+        base: String,
+        title: String,
+        body: String,
+        is_draft: bool,
+    ) -> anyhow::Result<String> {
+        let res = self
+            .octo
+            .pulls(self.org.clone(), self.repo.clone())
+            .create(title, full_branch, base)
+            .body(body)
+            .draft(Some(is_draft))
+            .send()
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(res
+            .html_url
+            .map(|u| u.to_string())
+            .unwrap_or_else(|| "Unknown URL".to_string()))
+    }
+
+    async fn pr_for_branch(&self, full_branch: &str) -> anyhow::Result<Option<Pr>> {
+        let branches = HashSet::from([full_branch.to_string()]);
+        Ok(self.head_ref_prs(&branches, true).await?.into_iter().next())
+    }
+
+    async fn prs_for_branches(&self, branches: &HashSet<String>) -> anyhow::Result<Vec<Pr>> {
+        self.head_ref_prs(branches, false).await
+    }
+
+    async fn update_pr(
+        &self,
+        pr: &Pr,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()> {
+        let mut input = serde_json::json!({ "pullRequestId": pr.id });
+        if let Some(title) = title {
+            input["title"] = serde_json::json!(title);
+        }
+        if let Some(body) = body {
+            input["body"] = serde_json::json!(body);
+        }
+        self.mutate("updatePullRequest", "UpdatePullRequestInput", input)
+            .await
+    }
+
+    async fn add_pr_metadata(&self, pr: &Pr, metadata: &PrMetadata) -> anyhow::Result<()> {
+        // Github won't take a review request for the PR's own author.
+        let reviewers = metadata
+            .reviewers
+            .iter()
+            .filter(|r| **r != self.current_user)
+            .cloned()
+            .collect();
+        // Each part goes on by itself, a team the token can't see shouldn't cost the labels.
+        let mut errors = vec![];
+        if let Err(e) = self.request_reviewers(pr.branch.clone(), reviewers).await {
+            errors.push(format!("requesting reviewers: {}", e));
         }
+        let issues = self.octo.issues(self.org.clone(), self.repo.clone());
+        if !metadata.labels.is_empty() {
+            if let Err(e) = issues.add_labels(pr.number, &metadata.labels).await {
+                errors.push(format!("adding labels: {}", e));
+            }
+        }
+        if !metadata.assignees.is_empty() {
+            let assignees: Vec<&str> = metadata.assignees.iter().map(|a| a.as_str()).collect();
+            if let Err(e) = issues.add_assignees(pr.number, &assignees).await {
+                errors.push(format!("adding assignees: {}", e));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(anyhow::anyhow!(errors.join(", "))),
+        }
+    }
+
+    async fn land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        let pr = self
+            .pr_for_branch(&full_branch)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no PR found for {}", full_branch))?;
+
+        let pulls = self.octo.pulls(self.org.clone(), self.repo.clone());
+        let mut merge = pulls.merge(pr.number).method(match opts.method.as_str() {
+            "MERGE" => MergeMethod::Merge,
+            "REBASE" => MergeMethod::Rebase,
+            _ => MergeMethod::Squash,
+        });
+        if let Some(title) = &opts.title {
+            merge = merge.title(title);
+        }
+        if let Some(message) = &opts.message {
+            merge = merge.message(message);
+        }
+        let res = merge.send().await.map_err(anyhow::Error::msg)?;
+
+        if !res.merged {
+            return Err(anyhow::anyhow!(
+                "{} wasn't merged: {}",
+                pr.url,
+                res.message.unwrap_or_default()
+            ));
+        }
+        Ok(())
+    }
+
+    async fn auto_land_pr(&self, full_branch: String, opts: &MergeOptions) -> anyhow::Result<()> {
+        if self.has_merge_queue(&full_branch).await? {
+            self.enqueue(full_branch.clone()).await?;
+            match self.merge_queue_position(&full_branch).await {
+                Some(position) => println!(
+                    "Added {} to the merge queue at position {}",
+                    full_branch, position
+                ),
+                None => println!("Added {} to the merge queue", full_branch),
+            }
+        } else {
+            self.enable_auto_merge(full_branch.clone(), opts).await?;
+            println!("Enabled auto-merge for {}", full_branch);
+        }
+        Ok(())
+    }
+
+    async fn unresolved_threads(&self, pr: &Pr) -> anyhow::Result<usize> {
+        self.unresolved_review_threads(pr).await
+    }
+
+    async fn review_threads(&self, pr: &Pr) -> anyhow::Result<Vec<ReviewThread>> {
+        self.pr_review_threads(pr).await
+    }
+
+    async fn change_base(&self, full_branch: String, new_base: String) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        self.mutate(
+            "updatePullRequest",
+            "UpdatePullRequestInput",
+            serde_json::json!({ "pullRequestId": id, "baseRefName": new_base }),
+        )
+        .await
+    }
+
+    async fn create_issue(&self, title: &str, body: &str) -> anyhow::Result<Issue> {
+        let res = self
+            .octo
+            .issues(self.org.clone(), self.repo.clone())
+            .create(title)
+            .body(body)
+            .send()
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(res.into())
+    }
+
+    async fn get_issue(&self, number: u64) -> anyhow::Result<Issue> {
+        let res = self
+            .octo
+            .issues(self.org.clone(), self.repo.clone())
+            .get(number)
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(res.into())
+    }
+
+    async fn get_issues(&self) -> anyhow::Result<Vec<Issue>> {
+        let res = self
+            .octo
+            .issues(self.org.clone(), self.repo.clone())
+            .list()
+            .creator(self.current_user.clone())
+            .per_page(100)
+            .send()
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(res
+            .into_iter()
+            .filter(|x| x.pull_request.is_none())
+            .map(Issue::from)
+            .collect())
+    }
+
+    async fn set_issue_closed(&self, number: u64) -> anyhow::Result<()> {
+        self.octo
+            .issues(self.org.clone(), self.repo.clone())
+            .update(number)
+            .state(IssueState::Closed)
+            .send()
+            .await
+            .map_err(anyhow::Error::msg)?;
+        Ok(())
     }
 }
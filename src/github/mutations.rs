@@ -0,0 +1,230 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    forge::{Forge, MergeOptions},
+    github::GithubRepo,
+};
+
+#[derive(Deserialize)]
+struct NodeId {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct MergeQueueData {
+    node: Option<MergeQueueNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeQueueNode {
+    is_merge_queue_enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct MergeQueueEntryData {
+    node: Option<MergeQueueEntryNode>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeQueueEntryNode {
+    merge_queue_entry: Option<MergeQueueEntry>,
+}
+
+#[derive(Deserialize)]
+struct MergeQueueEntry {
+    position: u64,
+}
+
+#[derive(Deserialize)]
+struct UserData {
+    user: Option<NodeId>,
+}
+
+#[derive(Deserialize)]
+struct TeamData {
+    organization: Option<OrganizationTeam>,
+}
+
+#[derive(Deserialize)]
+struct OrganizationTeam {
+    team: Option<NodeId>,
+}
+
+impl GithubRepo {
+    pub(crate) async fn pr_node_id(&self, full_branch: &str) -> anyhow::Result<String> {
+        self.pr_for_branch(full_branch)
+            .await?
+            .map(|pr| pr.id)
+            .ok_or_else(|| anyhow::anyhow!("no PR found for {}", full_branch))
+    }
+
+    pub async fn update_title_and_body(
+        &self,
+        full_branch: String,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        let mut input = json!({ "pullRequestId": id });
+        if let Some(title) = title {
+            input["title"] = json!(title);
+        }
+        if let Some(body) = body {
+            input["body"] = json!(body);
+        }
+        self.mutate("updatePullRequest", "UpdatePullRequestInput", input)
+            .await
+    }
+
+    pub async fn mark_ready_for_review(&self, full_branch: String) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        self.mutate(
+            "markPullRequestReadyForReview",
+            "MarkPullRequestReadyForReviewInput",
+            json!({ "pullRequestId": id }),
+        )
+        .await
+    }
+
+    pub async fn convert_to_draft(&self, full_branch: String) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        self.mutate(
+            "convertPullRequestToDraft",
+            "ConvertPullRequestToDraftInput",
+            json!({ "pullRequestId": id }),
+        )
+        .await
+    }
+
+    pub async fn enable_auto_merge(
+        &self,
+        full_branch: String,
+        opts: &MergeOptions,
+    ) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        let mut input = json!({ "pullRequestId": id, "mergeMethod": opts.method });
+        if let Some(title) = &opts.title {
+            input["commitHeadline"] = json!(title);
+        }
+        if let Some(message) = &opts.message {
+            input["commitBody"] = json!(message);
+        }
+        self.mutate(
+            "enablePullRequestAutoMerge",
+            "EnablePullRequestAutoMergeInput",
+            input,
+        )
+        .await
+    }
+
+    // The queue merges the PR however the queue is set up, so there's no method to pass.
+    pub async fn enqueue(&self, full_branch: String) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        self.mutate(
+            "enqueuePullRequest",
+            "EnqueuePullRequestInput",
+            json!({ "pullRequestId": id }),
+        )
+        .await
+    }
+
+    // Github Enterprise Server versions from before merge queues don't have the field, and
+    // those repos can't have a queue.
+    pub async fn has_merge_queue(&self, full_branch: &str) -> anyhow::Result<bool> {
+        let id = self.pr_node_id(full_branch).await?;
+        let data: Option<MergeQueueData> = self
+            .graphql(
+                "query($id: ID!) { node(id: $id) { ... on PullRequest { isMergeQueueEnabled } } }",
+                json!({ "id": id }),
+            )
+            .await
+            .ok();
+        Ok(data
+            .and_then(|d| d.node)
+            .map(|n| n.is_merge_queue_enabled)
+            .unwrap_or(false))
+    }
+
+    // Where the PR is in the queue, as github numbers it. Only worth showing, so a server
+    // without merge queues (or any other failure) just gives None.
+    pub async fn merge_queue_position(&self, full_branch: &str) -> Option<u64> {
+        let id = self.pr_node_id(full_branch).await.ok()?;
+        let data: MergeQueueEntryData = self
+            .graphql(
+                "query($id: ID!) { node(id: $id) { ... on PullRequest { mergeQueueEntry { position } } } }",
+                json!({ "id": id }),
+            )
+            .await
+            .ok()?;
+        data.node?.merge_queue_entry.map(|e| e.position)
+    }
+
+    pub async fn disable_auto_merge(&self, full_branch: String) -> anyhow::Result<()> {
+        let id = self.pr_node_id(&full_branch).await?;
+        self.mutate(
+            "disablePullRequestAutoMerge",
+            "DisablePullRequestAutoMergeInput",
+            json!({ "pullRequestId": id }),
+        )
+        .await
+    }
+
+    // Reviewers are user logins, or `org/team` for teams. Existing reviewers are kept.
+    pub async fn request_reviewers(
+        &self,
+        full_branch: String,
+        reviewers: Vec<String>,
+    ) -> anyhow::Result<()> {
+        if reviewers.is_empty() {
+            return Ok(());
+        }
+        let id = self.pr_node_id(&full_branch).await?;
+        let mut user_ids = vec![];
+        let mut team_ids = vec![];
+        for reviewer in reviewers {
+            match reviewer.split_once('/') {
+                Some((org, team)) => team_ids.push(self.team_node_id(org, team).await?),
+                None => user_ids.push(self.user_node_id(&reviewer).await?),
+            }
+        }
+        self.mutate(
+            "requestReviews",
+            "RequestReviewsInput",
+            json!({
+                "pullRequestId": id,
+                "userIds": user_ids,
+                "teamIds": team_ids,
+                "union": true,
+            }),
+        )
+        .await
+    }
+
+    async fn user_node_id(&self, login: &str) -> anyhow::Result<String> {
+        let data: UserData = self
+            .graphql(
+                "query($login: String!) { user(login: $login) { id } }",
+                json!({ "login": login }),
+            )
+            .await?;
+        data.user
+            .map(|u| u.id)
+            .ok_or_else(|| anyhow::anyhow!("no user {}", login))
+    }
+
+    async fn team_node_id(&self, org: &str, team: &str) -> anyhow::Result<String> {
+        let data: TeamData = self
+            .graphql(
+                "query($org: String!, $team: String!) { organization(login: $org) { team(slug: $team) { id } } }",
+                json!({ "org": org, "team": team }),
+            )
+            .await?;
+        data.organization
+            .and_then(|o| o.team)
+            .map(|t| t.id)
+            .ok_or_else(|| anyhow::anyhow!("no team {}/{}", org, team))
+    }
+}
@@ -0,0 +1,287 @@
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    forge::{Pr, ReviewComment, ReviewThread},
+    github::{graphql::Connection, GithubRepo},
+};
+
+// Threads and the PR's own comments are paged through separately, each has its own cursor.
+const REVIEW_THREADS_QUERY: &str = "
+query($id: ID!, $cursor: String) {
+    node(id: $id) {
+        ... on PullRequest {
+            reviewThreads(first: 100, after: $cursor) {
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+                nodes {
+                    id
+                    isResolved
+                    path
+                    line
+                    originalLine
+                    comments(first: 100) {
+                        nodes {
+                            id
+                            author { login }
+                            body
+                            url
+                            diffHunk
+                            originalCommit { oid }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}";
+
+const PR_COMMENTS_QUERY: &str = "
+query($id: ID!, $cursor: String) {
+    node(id: $id) {
+        ... on PullRequest {
+            comments(first: 100, after: $cursor) {
+                pageInfo {
+                    hasNextPage
+                    endCursor
+                }
+                nodes {
+                    id
+                    author { login }
+                    body
+                    url
+                }
+            }
+        }
+    }
+}";
+
+const LAST_COMMENT_QUERY: &str = "
+query($id: ID!) {
+    node(id: $id) {
+        ... on PullRequestReviewThread {
+            comments(last: 1) {
+                nodes {
+                    id
+                }
+            }
+        }
+    }
+}";
+
+// Github has no "done" reaction, a thumbs up on the last comment is how it's usually said.
+const DONE_REACTION: &str = "THUMBS_UP";
+
+#[derive(Deserialize, Debug)]
+struct NodeData<T> {
+    node: Option<T>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReviewThreadsNode {
+    review_threads: Connection<ThreadNode>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommentsNode {
+    comments: Connection<CommentNode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThreadNode {
+    id: String,
+    is_resolved: bool,
+    path: String,
+    // Null once the line is gone from the PR's diff, the original line still says where it was.
+    line: Option<u64>,
+    original_line: Option<u64>,
+    comments: Connection<CommentNode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CommentNode {
+    id: String,
+    // Null for deleted accounts.
+    author: Option<Author>,
+    body: String,
+    url: String,
+    #[serde(default)]
+    diff_hunk: Option<String>,
+    #[serde(default)]
+    original_commit: Option<CommitOid>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LastComment {
+    comments: Connection<CommentId>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommentId {
+    id: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Author {
+    login: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitOid {
+    oid: String,
+}
+
+impl From<CommentNode> for ReviewComment {
+    fn from(node: CommentNode) -> ReviewComment {
+        ReviewComment {
+            id: node.id,
+            author: node
+                .author
+                .map(|a| a.login)
+                .unwrap_or_else(|| "ghost".to_string()),
+            body: node.body,
+            url: node.url,
+        }
+    }
+}
+
+impl GithubRepo {
+    async fn thread_nodes(&self, pr: &Pr) -> anyhow::Result<Vec<ThreadNode>> {
+        let mut threads = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: NodeData<ReviewThreadsNode> = self
+                .graphql(
+                    REVIEW_THREADS_QUERY,
+                    json!({ "id": pr.id, "cursor": cursor }),
+                )
+                .await?;
+            let node = data
+                .node
+                .ok_or_else(|| anyhow::anyhow!("no PR {}", pr.url))?;
+            cursor = node.review_threads.next_cursor();
+            threads.extend(node.review_threads.into_nodes());
+            if cursor.is_none() {
+                return Ok(threads);
+            }
+        }
+    }
+
+    async fn comment_nodes(&self, pr: &Pr) -> anyhow::Result<Vec<CommentNode>> {
+        let mut comments = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let data: NodeData<CommentsNode> = self
+                .graphql(PR_COMMENTS_QUERY, json!({ "id": pr.id, "cursor": cursor }))
+                .await?;
+            let node = data
+                .node
+                .ok_or_else(|| anyhow::anyhow!("no PR {}", pr.url))?;
+            cursor = node.comments.next_cursor();
+            comments.extend(node.comments.into_nodes());
+            if cursor.is_none() {
+                return Ok(comments);
+            }
+        }
+    }
+
+    pub(crate) async fn unresolved_review_threads(&self, pr: &Pr) -> anyhow::Result<usize> {
+        Ok(self
+            .thread_nodes(pr)
+            .await?
+            .iter()
+            .filter(|t| !t.is_resolved)
+            .count())
+    }
+
+    pub(crate) async fn pr_review_threads(&self, pr: &Pr) -> anyhow::Result<Vec<ReviewThread>> {
+        let mut threads: Vec<ReviewThread> = self
+            .thread_nodes(pr)
+            .await?
+            .into_iter()
+            .map(|t| {
+                let comments: Vec<CommentNode> = t.comments.into_nodes().collect();
+                let first = comments.first();
+                ReviewThread {
+                    commit: first
+                        .and_then(|c| c.original_commit.as_ref())
+                        .map(|c| c.oid.clone()),
+                    diff_hunk: first.and_then(|c| c.diff_hunk.clone()),
+                    id: t.id,
+                    path: Some(t.path),
+                    line: t.line.or(t.original_line),
+                    resolved: t.is_resolved,
+                    comments: comments.into_iter().map(ReviewComment::from).collect(),
+                }
+            })
+            .collect();
+        threads.extend(
+            self.comment_nodes(pr)
+                .await?
+                .into_iter()
+                .map(|c| ReviewThread {
+                    id: c.id.clone(),
+                    path: None,
+                    line: None,
+                    commit: None,
+                    resolved: false,
+                    diff_hunk: None,
+                    comments: vec![c.into()],
+                }),
+        );
+        Ok(threads)
+    }
+
+    pub(crate) async fn reply_to_thread(&self, thread_id: &str, body: &str) -> anyhow::Result<()> {
+        self.mutate(
+            "addPullRequestReviewThreadReply",
+            "AddPullRequestReviewThreadReplyInput",
+            json!({ "pullRequestReviewThreadId": thread_id, "body": body }),
+        )
+        .await
+    }
+
+    pub(crate) async fn resolve_thread(&self, thread_id: &str) -> anyhow::Result<()> {
+        self.mutate(
+            "resolveReviewThread",
+            "ResolveReviewThreadInput",
+            json!({ "threadId": thread_id }),
+        )
+        .await
+    }
+
+    pub(crate) async fn unresolve_thread(&self, thread_id: &str) -> anyhow::Result<()> {
+        self.mutate(
+            "unresolveReviewThread",
+            "UnresolveReviewThreadInput",
+            json!({ "threadId": thread_id }),
+        )
+        .await
+    }
+
+    // Reacts to a comment, review comment or issue comment alike.
+    pub(crate) async fn mark_done(&self, comment_id: &str) -> anyhow::Result<()> {
+        self.mutate(
+            "addReaction",
+            "AddReactionInput",
+            json!({ "subjectId": comment_id, "content": DONE_REACTION }),
+        )
+        .await
+    }
+
+    // For marking a thread done when only its id is known.
+    pub(crate) async fn last_comment_id(&self, thread_id: &str) -> anyhow::Result<String> {
+        let data: NodeData<LastComment> = self
+            .graphql(LAST_COMMENT_QUERY, json!({ "id": thread_id }))
+            .await?;
+        data.node
+            .and_then(|n| n.comments.into_nodes().next())
+            .map(|c| c.id)
+            .ok_or_else(|| anyhow::anyhow!("no review thread {}", thread_id))
+    }
+}
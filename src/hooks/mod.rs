@@ -0,0 +1,144 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::Serialize;
+
+use crate::{
+    color,
+    config::{self, get_saved_config},
+    git::{self, ParsedBranch},
+};
+
+// Points in gg's workflow where user scripts can run. Scripts live in `.gg/hooks/<name>`
+// in the repo, or are listed under "hooks" in the GG_CONFIG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Hook {
+    PreSubmit,
+    PreLand,
+    PostLand,
+    PostRebase,
+    PostCleanup,
+    PostCloseIssue,
+}
+
+impl Hook {
+    fn name(&self) -> &'static str {
+        match self {
+            Hook::PreSubmit => "pre-submit",
+            Hook::PreLand => "pre-land",
+            Hook::PostLand => "post-land",
+            Hook::PostRebase => "post-rebase",
+            Hook::PostCleanup => "post-cleanup",
+            Hook::PostCloseIssue => "post-close-issue",
+        }
+    }
+
+    // A failing pre hook aborts the operation, a failing post hook only warns.
+    fn is_pre(&self) -> bool {
+        matches!(self, Hook::PreSubmit | Hook::PreLand)
+    }
+}
+
+// Written as JSON to the hook's stdin.
+#[derive(Debug, Serialize)]
+pub(crate) struct HookPayload {
+    pub(crate) event: String,
+    pub(crate) branch: String,
+    pub(crate) stack: Vec<String>,
+    pub(crate) pr: Option<u64>,
+    pub(crate) issue: Option<u64>,
+}
+
+impl HookPayload {
+    pub(crate) fn for_branch(branch: &ParsedBranch, pr: Option<u64>) -> HookPayload {
+        HookPayload {
+            event: String::new(),
+            branch: branch.full(),
+            stack: git::get_sorted_matching_branches(&branch.base)
+                .iter()
+                .map(|b| b.full())
+                .collect(),
+            pr,
+            issue: match config::get_selected_issue_number() {
+                0 => None,
+                x => Some(x),
+            },
+        }
+    }
+
+    pub(crate) fn with_issue(mut self, issue: u64) -> HookPayload {
+        self.issue = Some(issue);
+        self
+    }
+}
+
+pub(crate) fn run(hook: Hook, mut payload: HookPayload) -> anyhow::Result<()> {
+    payload.event = hook.name().to_string();
+    let input = serde_json::to_string(&payload).map_err(anyhow::Error::msg)?;
+    for cmd in commands_for(hook) {
+        let ok = run_one(hook, &cmd, &input);
+        if ok {
+            continue;
+        }
+        if hook.is_pre() {
+            return Err(anyhow::anyhow!(
+                "{} hook '{}' failed, aborting",
+                hook.name(),
+                cmd
+            ));
+        }
+        println!(
+            "{}",
+            color::yellow(format!("{} hook '{}' failed", hook.name(), cmd))
+        );
+    }
+    Ok(())
+}
+
+fn run_one(hook: Hook, cmd: &str, input: &str) -> bool {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .env("GG_HOOK", hook.name())
+        .current_dir(config::get_repo_root_path())
+        .stdin(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            println!("could not run {} hook '{}': {}", hook.name(), cmd, e);
+            return false;
+        }
+    };
+    // The hook is free to ignore its stdin, so a broken pipe here is fine.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child
+        .wait()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+fn commands_for(hook: Hook) -> Vec<String> {
+    let mut cmds = vec![];
+    let script = hooks_dir().join(hook.name());
+    if script.is_file() {
+        cmds.push(shell_quote(&script));
+    }
+    if let Some(configured) = get_saved_config().hooks.get(hook.name()) {
+        cmds.extend(configured.iter().cloned());
+    }
+    cmds
+}
+
+fn hooks_dir() -> PathBuf {
+    Path::new(config::get_repo_root_path().as_str())
+        .join(".gg")
+        .join("hooks")
+}
+
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', "'\\''"))
+}
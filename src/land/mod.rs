@@ -0,0 +1,419 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    color,
+    config::{self, get_saved_config},
+    forge::{self, Forge, MergeOptions, Pr},
+    git::{self, parse_branch, ParsedBranch},
+    git_rebase,
+    hooks::{self, Hook, HookPayload},
+    record,
+};
+
+// How often a PR is polled while waiting for it to become mergeable, and for how long.
+const POLL_SECS: u64 = 15;
+const WAIT_TIMEOUT_SECS: u64 = 30 * 60;
+
+// What `gg land` was asked for on top of the repo's config.
+#[derive(Debug, Default)]
+pub(crate) struct LandOptions {
+    // Land past failed pre-land checks.
+    pub(crate) force: bool,
+    pub(crate) method: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) message: Option<String>,
+}
+
+// Progress of a `gg land --stack`, so a stopped run can pick up where it left off.
+#[derive(Serialize, Deserialize, Debug)]
+struct StackLandState {
+    base: String,
+    // Parts still to land, bottom first.
+    parts: Vec<String>,
+    // Once a part has landed, the rest need moving onto the new main before they can land.
+    landed_any: bool,
+}
+
+fn state_file_path() -> PathBuf {
+    git::git_path("GG_LAND_STACK")
+}
+
+// No file means no stack is being landed. One that can't be read (cut short by a full disk,
+// say) is an error, resuming from a guess could land the wrong parts.
+fn load_state() -> anyhow::Result<Option<StackLandState>> {
+    let path = state_file_path();
+    let buf = match fs::read_to_string(&path) {
+        Ok(buf) => buf,
+        Err(_) => return Ok(None),
+    };
+    serde_json::from_str(buf.as_str()).map(Some).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is broken ({}), run 'gg land --abort' to start over",
+            path.display(),
+            e
+        )
+    })
+}
+
+fn save_state(state: &StackLandState) {
+    fs::write(
+        state_file_path(),
+        serde_json::to_string_pretty(state).unwrap(),
+    )
+    .expect("writing land state");
+}
+
+pub(crate) fn clear_state() {
+    fs::remove_file(state_file_path()).ok();
+}
+
+// Lands one part: merges its PR, points any stacks built on it at main, then deletes it.
+// Returns false if the pre-land checks (unless forced past), the pre-land hook or the merge
+// itself stopped it.
+pub(crate) async fn land_branch(
+    forge: &dyn Forge,
+    branch: &ParsedBranch,
+    pr: &Pr,
+    opts: &LandOptions,
+) -> bool {
+    let merge_opts = match merge_options(pr, opts) {
+        Ok(merge_opts) => merge_opts,
+        Err(e) => {
+            println!("{}", color::red(e.to_string()));
+            return false;
+        }
+    };
+    let force = opts.force;
+    let failures = failed_checks(forge, branch, pr).await;
+    if !failures.is_empty() {
+        print_report(pr, &failures, force);
+        if !force {
+            return false;
+        }
+        for failure in &failures {
+            record::write_status(format!("Forced land of {}: {}", pr.url, failure), false);
+        }
+    }
+    if let Err(e) = hooks::run(
+        Hook::PreLand,
+        HookPayload::for_branch(branch, Some(pr.number)),
+    ) {
+        println!("{}", color::red(e.to_string()));
+        return false;
+    }
+    let post_land = HookPayload::for_branch(branch, Some(pr.number));
+    if let Err(e) = forge.land_pr(branch.full(), &merge_opts).await {
+        println!(
+            "{}",
+            color::red(format!("Couldn't land {}: {}", pr.url, e))
+        );
+        return false;
+    }
+    let mainbr = get_saved_config().repo_main_branch;
+    for dependent in git::get_dependent_stacks(branch) {
+        println!(
+            "Retargeting {} onto {}, rebase it with 'gg rebase -o origin/{}'",
+            dependent.full(),
+            mainbr,
+            mainbr
+        );
+        retarget(forge, &dependent.full(), &mainbr).await;
+        config::update_stack_dependency(&dependent.base, None);
+    }
+    git::fetch_main();
+    // The branch is going away, so uncommitted changes on it come along to main.
+    match git::move_to(&format!("origin/{}", mainbr)) {
+        Ok(()) => {
+            git::delete_branch_all(branch.full());
+            git::delete_branch_all(branch.start());
+        }
+        Err(e) => println!(
+            "{}",
+            color::yellow(format!("Landed, but kept {}'s branches: {}", branch.full(), e))
+        ),
+    }
+    record::write_status(format!("Landed: {}", pr.title), false);
+    hooks::run(Hook::PostLand, post_land).expect("post-land hook");
+    true
+}
+
+// Hands the PR to the forge to merge once it's approved and green, so those checks are left
+// to it. The branches stay until it has landed, 'gg cleanup' picks them up after. Returns
+// false if the forge wouldn't take it (auto-merge turned off for the repo, say).
+pub(crate) async fn auto_land_branch(
+    forge: &dyn Forge,
+    branch: &ParsedBranch,
+    pr: &Pr,
+    opts: &LandOptions,
+) -> bool {
+    let merge_opts = match merge_options(pr, opts) {
+        Ok(merge_opts) => merge_opts,
+        Err(e) => {
+            println!("{}", color::red(e.to_string()));
+            return false;
+        }
+    };
+    if let Err(e) = hooks::run(
+        Hook::PreLand,
+        HookPayload::for_branch(branch, Some(pr.number)),
+    ) {
+        println!("{}", color::red(e.to_string()));
+        return false;
+    }
+    if let Err(e) = forge.auto_land_pr(branch.full(), &merge_opts).await {
+        println!(
+            "{}",
+            color::red(format!("Couldn't hand {} to the forge to merge: {}", pr.url, e))
+        );
+        return false;
+    }
+    record::write_status(format!("Queued to land: {}", pr.title), false);
+    true
+}
+
+// What's keeping the PR from landing, one line per failed check. Checks turned off in the
+// repo's land_checks config are skipped.
+async fn failed_checks(forge: &dyn Forge, branch: &ParsedBranch, pr: &Pr) -> Vec<String> {
+    let checks = get_saved_config().land_checks;
+    let mut failures = vec![];
+    // No review decision means the repo doesn't require reviews.
+    if checks.approved {
+        if let Some(decision) = pr.review_decision.as_deref() {
+            if decision != "APPROVED" {
+                failures.push(format!("review is {}, not APPROVED", decision));
+            }
+        }
+    }
+    if checks.checks_green && !matches!(pr.test_status.as_str(), "SUCCESS" | "N/A") {
+        failures.push(format!("checks are {}, not SUCCESS", pr.test_status));
+    }
+    if checks.mergeable && pr.mergeable != "MERGEABLE" {
+        failures.push(format!("mergeable is {}, not MERGEABLE", pr.mergeable));
+    }
+    if checks.threads_resolved {
+        match forge.unresolved_threads(pr).await {
+            Ok(0) => {}
+            Ok(n) => failures.push(format!("{} review thread(s) unresolved", n)),
+            Err(e) => failures.push(format!("couldn't look up review threads: {}", e)),
+        }
+    }
+    if checks.no_wip_commits {
+        for subject in git::commit_subjects(&branch.start(), &branch.full()) {
+            if is_wip(&subject) {
+                failures.push(format!("commit '{}' isn't ready to land", subject));
+            }
+        }
+    }
+    failures
+}
+
+fn is_wip(subject: &str) -> bool {
+    subject.starts_with("fixup!")
+        || subject.starts_with("squash!")
+        || subject.to_uppercase().starts_with("WIP")
+}
+
+fn print_report(pr: &Pr, failures: &[String], force: bool) {
+    let heading = match force {
+        true => format!("Landing {} anyway (--force):", pr.url),
+        false => format!("Not landing {}:", pr.url),
+    };
+    println!("{}", color::bold(heading));
+    for failure in failures {
+        println!("  {} {}", color::red("x"), failure);
+    }
+    if !force {
+        println!("Fix these, or run with --force to land it anyway");
+    }
+}
+
+// The merge method and commit title/message, from the command line or else the repo config.
+fn merge_options(pr: &Pr, opts: &LandOptions) -> anyhow::Result<MergeOptions> {
+    let cfg = get_saved_config();
+    let method = opts
+        .method
+        .clone()
+        .unwrap_or(cfg.merge_method)
+        .to_uppercase();
+    if !matches!(method.as_str(), "MERGE" | "SQUASH" | "REBASE") {
+        anyhow::bail!(
+            "unknown merge method {}, want MERGE, SQUASH or REBASE",
+            method
+        );
+    }
+    let (title_template, message_template) = (cfg.merge_title_template, cfg.merge_message_template);
+    let resolves = match cfg.linked_issue {
+        Some(0) | None => String::new(),
+        Some(issue) => format!("Resolves #{}", issue),
+    };
+    let fill = |template: String| {
+        template
+            .replace("{title}", &pr.title)
+            .replace("{number}", &pr.number.to_string())
+            .replace("{body}", &pr.body)
+            .replace("{resolves}", &resolves)
+            .trim()
+            .to_string()
+    };
+    let message = opts.message.clone().or_else(|| message_template.map(fill));
+    // A message needs a title to go with it, github's default one will do.
+    let title = opts
+        .title
+        .clone()
+        .or_else(|| title_template.map(fill))
+        .or_else(|| {
+            message
+                .as_ref()
+                .map(|_| format!("{} (#{})", pr.title, pr.number))
+        });
+    Ok(MergeOptions {
+        method,
+        title,
+        message,
+    })
+}
+
+async fn retarget(forge: &dyn Forge, full_branch: &str, base: &str) {
+    let pr = forge
+        .pr_for_branch(full_branch)
+        .await
+        .expect("error getting PR");
+    if pr.is_some() {
+        forge
+            .change_base(full_branch.to_string(), base.to_string())
+            .await
+            .expect("error changing base");
+    }
+}
+
+// Lands every part of the current stack bottom-up (or carries on with a stopped run). Each
+// part after the first is retargeted to main, restacked onto it, pushed and waited on before
+// it's merged. Returns whether the whole stack landed.
+pub(crate) async fn land_stack(forge: &dyn Forge, opts: &LandOptions) -> bool {
+    let mut state = match load_state() {
+        Ok(Some(state)) => {
+            println!("Resuming landing {}", state.base);
+            state
+        }
+        Err(e) => {
+            println!("{}", color::red(e.to_string()));
+            return false;
+        }
+        Ok(None) => {
+            let cur = git::current_parsed_branch();
+            if let Some(dep) = git::stack_dependency(&cur.base) {
+                println!(
+                    "{} builds on {}, land that first (or drop it with 'gg depend --clear')",
+                    cur.base, dep
+                );
+                return false;
+            }
+            StackLandState {
+                parts: git::get_sorted_matching_branches(&cur.base)
+                    .into_iter()
+                    .map(|b| b.full())
+                    .collect(),
+                base: cur.base,
+                landed_any: false,
+            }
+        }
+    };
+    save_state(&state);
+
+    let mainbr = get_saved_config().repo_main_branch;
+    let existing = git::all_branches();
+    while let Some(full) = state.parts.first().cloned() {
+        let part = parse_branch(full.clone());
+        if !existing.contains(&full) {
+            println!("{} is gone, skipping it", full);
+            state.parts.remove(0);
+            save_state(&state);
+            continue;
+        }
+        if state.landed_any {
+            retarget(forge, &full, &mainbr).await;
+            if let Err(e) = restack_onto_main(&part, &mainbr) {
+                stopped(&full, e.to_string());
+                return false;
+            }
+            git::push(vec![part.full(), part.start()], true);
+            forge::stack_nav::update(forge, &state.base).await;
+            // Give CI a moment to pick up the push before its status means anything.
+            tokio::time::sleep(Duration::from_secs(POLL_SECS)).await;
+        }
+        let pr = match wait_until_settled(forge, &full).await {
+            Ok(pr) => pr,
+            Err(reason) => {
+                stopped(&full, reason);
+                return false;
+            }
+        };
+        if !land_branch(forge, &part, &pr, opts).await {
+            stopped(&full, "it isn't ready to land".to_string());
+            return false;
+        }
+        state.parts.remove(0);
+        state.landed_any = true;
+        save_state(&state);
+    }
+    clear_state();
+    println!("{}", color::green(format!("Landed all of {}", state.base)));
+    true
+}
+
+fn stopped(full_branch: &str, reason: String) {
+    println!(
+        "{}",
+        color::red(format!("Stopped landing at {}: {}", full_branch, reason))
+    );
+    println!(
+        "Fix it and run 'gg land --stack' again to carry on (or 'gg land --abort' to drop it)"
+    );
+}
+
+// Moves a part onto the freshly fetched main, dropping commits that already landed. A part
+// that's already there (say after a conflict was fixed with 'gg rebase --continue') is left be.
+fn restack_onto_main(part: &ParsedBranch, mainbr: &str) -> anyhow::Result<()> {
+    let onto = format!("origin/{}", mainbr);
+    if git::get_commit_hash(part.start()) == git::get_commit_hash(onto.clone()) {
+        return Ok(());
+    }
+    git::with_autostash(|| {
+        git::checkout(&part.full())?;
+        git_rebase::start_rebase(Some(onto), None)
+    })
+}
+
+// Polls the PR until github (or whichever forge) has worked out whether it can be merged and
+// its checks have finished, or one of them has already failed. Whether it's fit to land is
+// up to the pre-land checks.
+async fn wait_until_settled(forge: &dyn Forge, full_branch: &str) -> Result<Pr, String> {
+    let deadline = Instant::now() + Duration::from_secs(WAIT_TIMEOUT_SECS);
+    loop {
+        let pr = forge
+            .pr_for_branch(full_branch)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "it has no open PR".to_string())?;
+        let failed =
+            pr.mergeable == "CONFLICTING" || matches!(pr.test_status.as_str(), "FAILURE" | "ERROR");
+        let waiting =
+            pr.mergeable == "UNKNOWN" || matches!(pr.test_status.as_str(), "PENDING" | "EXPECTED");
+        if failed || !waiting {
+            return Ok(pr);
+        }
+        if Instant::now() > deadline {
+            return Err(format!("timed out waiting on {}", pr.url));
+        }
+        println!(
+            "Waiting on {} (mergeable: {}, checks: {})",
+            pr.url, pr.mergeable, pr.test_status
+        );
+        tokio::time::sleep(Duration::from_secs(POLL_SECS)).await;
+    }
+}
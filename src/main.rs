@@ -1,26 +1,34 @@
+mod auth;
+mod codeowners;
 mod color;
 mod config;
+mod db;
 mod file;
+mod forge;
+mod gg_id;
 mod git;
 mod git_rebase;
 mod github;
+mod hooks;
+mod land;
 mod pomodoro;
 mod record;
+mod reviews;
 mod terminal;
 use anyhow::Result;
 use config::get_saved_config;
 use git::{current_parsed_branch, diff, status, sync};
-use git_rebase::{abort_rebase, continue_rebase, fixup_rebase, rebase_all_children, start_rebase};
-use github::{pr::Pr, GithubRepo};
-use std::{
-    collections::HashSet,
-    io::{self, Read, Write},
+use git_rebase::{
+    abort_rebase, continue_rebase, fixup_rebase, rebase_all_children, restack_children, start_rebase,
 };
+use forge::{Forge, MergeOptions, Pr, PrMetadata};
+use hooks::{Hook, HookPayload};
+use std::collections::{HashMap, HashSet};
 use structopt::StructOpt;
 
 use crate::{
     config::{get_full_config, update_prefix_and_split},
-    git::{current_branch, delete_branch_all, parse_branch},
+    git::{current_branch, delete_branch_all, parse_branch, ParsedBranch},
 };
 
 #[derive(StructOpt)]
@@ -29,6 +37,8 @@ use crate::{
     about = "A command line tool for organizing tasks and git commits/PRs"
 )]
 struct GG {
+    #[structopt(long, global = true, help = "only use PRs and issues cached in ~/.gg")]
+    offline: bool,
     #[structopt(subcommand)]
     cmd: Cmd,
 }
@@ -38,7 +48,10 @@ struct GG {
 enum Cmd {
     #[structopt(about = "Initialize this repo with the GG Config")]
     Init {},
-    #[structopt(about = "Checkout a branch", alias = "co")]
+    #[structopt(
+        about = "Checkout a branch (opens a picker over all stacks with no args)",
+        alias = "co"
+    )]
     Checkout {
         #[structopt(short, long)]
         next: bool,
@@ -71,6 +84,49 @@ enum Cmd {
         use_start: bool,
         #[structopt(short = "d", long = "draft")]
         is_draft: bool,
+        #[structopt(long = "reviewer", help = "request a review from a login or org/team, can be repeated")]
+        reviewers: Vec<String>,
+        #[structopt(long = "label", help = "add a label, can be repeated")]
+        labels: Vec<String>,
+        #[structopt(long, help = "start from the named PR template, or pick one if no name is given")]
+        template: Option<Option<String>>,
+        #[structopt(long, help = "edit the title and body of the branch's existing PR")]
+        edit: bool,
+        #[structopt(long = "discard-draft", help = "throw away the saved title and body for the branch")]
+        discard_draft: bool,
+    },
+    #[structopt(about = "Commit on the current part and restack the parts above it")]
+    Commit {
+        #[structopt(short, long)]
+        message: Option<String>,
+        #[structopt(short, long, about = "commit all changed files")]
+        all: bool,
+    },
+    #[structopt(about = "Amend the current part's last commit and restack the parts above it")]
+    Amend {
+        #[structopt(short, long)]
+        message: Option<String>,
+        #[structopt(short, long, about = "commit all changed files")]
+        all: bool,
+        #[structopt(long = "no-edit")]
+        no_edit: bool,
+    },
+    #[structopt(name = "pr-update", about = "Update the github pr for the current branch")]
+    PrUpdate {
+        #[structopt(long, help = "mark the PR ready for review")]
+        ready: bool,
+        #[structopt(long, help = "convert the PR to a draft")]
+        draft: bool,
+        #[structopt(long = "auto-merge", help = "enable auto-merge with MERGE, SQUASH or REBASE")]
+        auto_merge: Option<String>,
+        #[structopt(long = "no-auto-merge")]
+        no_auto_merge: bool,
+        #[structopt(short, long = "reviewer", help = "user login or org/team to request")]
+        reviewers: Vec<String>,
+        #[structopt(short, long)]
+        title: Option<String>,
+        #[structopt(short, long)]
+        body: Option<String>,
     },
     #[structopt(about = "Fetch the current master/main.")]
     Fetch {},
@@ -84,7 +140,31 @@ enum Cmd {
         all: bool,
     },
     #[structopt(about = "Land the current PR")]
-    Land {},
+    Land {
+        #[structopt(long, help = "land every part of the stack in order, or carry on with one that stopped")]
+        stack: bool,
+        #[structopt(long, help = "forget a stack that stopped part way through landing")]
+        abort: bool,
+        #[structopt(
+            long,
+            conflicts_with = "stack",
+            help = "have the forge merge the PR once it's ready, through the merge queue if there is one"
+        )]
+        auto: bool,
+        #[structopt(long, help = "land even if the pre-land checks fail, noting it in the status file")]
+        force: bool,
+        #[structopt(
+            long,
+            possible_values = &["merge", "squash", "rebase"],
+            case_insensitive = true,
+            help = "merge with MERGE, SQUASH or REBASE instead of the configured method"
+        )]
+        method: Option<String>,
+        #[structopt(long, help = "title for the merge commit")]
+        title: Option<String>,
+        #[structopt(long, help = "message for the merge commit")]
+        message: Option<String>,
+    },
     #[structopt(about = "Rebase the current branch onto master/main")]
     RebaseOld {
         #[structopt(short, long)]
@@ -139,6 +219,13 @@ enum Cmd {
         about = "Shows the list of changed files for the branch"
     )]
     Status {},
+    #[structopt(about = "List unresolved review threads and comments on your open PRs")]
+    Reviews {
+        #[structopt(long, help = "print file:line:col: lines for an editor's quickfix list")]
+        quickfix: bool,
+        #[structopt(subcommand)]
+        cmd: Option<ReviewsSubcommand>,
+    },
     #[structopt(about = "delete closed branches")]
     Cleanup {
         #[structopt(short = "f", long = "force")]
@@ -161,8 +248,25 @@ enum Cmd {
         #[structopt(about = "separator for splitting branch name")]
         separator: String,
     },
+    #[structopt(about = "Make the current stack build on another stack's part")]
+    Depend {
+        #[structopt(about = "Branch the first part of this stack builds on", short, long)]
+        on: Option<String>,
+        #[structopt(about = "Build on main again", long)]
+        clear: bool,
+    },
+    #[structopt(about = "Manage the github token gg uses")]
+    Auth(AuthSubcommand),
     #[structopt(about = "dumps debug info")]
     Debug {},
+    #[structopt(
+        about = "Adds a Gg-Id trailer to a commit message (run by the commit-msg hook)",
+        setting = structopt::clap::AppSettings::Hidden
+    )]
+    CommitMsg {
+        #[structopt(about = "commit message file")]
+        file: String,
+    },
 }
 
 #[derive(StructOpt, Debug)]
@@ -177,6 +281,35 @@ enum IssueSubcommand {
     List {},
 }
 
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Commands for managing the github token")]
+enum AuthSubcommand {
+    #[structopt(about = "Validate a token and save it to ~/.gg/credentials")]
+    Login {
+        #[structopt(short, long, help = "token to save (prompted for if missing)")]
+        token: Option<String>,
+    },
+    #[structopt(about = "Show where the token comes from and what it can do")]
+    Status {},
+    #[structopt(about = "Remove the token from ~/.gg/credentials")]
+    Logout {},
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Act on review threads, by the ids `gg reviews` lists")]
+enum ReviewsSubcommand {
+    #[structopt(about = "Walk the unresolved threads one at a time (github only)")]
+    Walk {},
+    #[structopt(about = "Reply to a review thread")]
+    Reply { thread: String, body: String },
+    #[structopt(about = "Resolve a review thread")]
+    Resolve { thread: String },
+    #[structopt(about = "Unresolve a review thread")]
+    Unresolve { thread: String },
+    #[structopt(about = "React to the last comment of a review thread to say it's done")]
+    Done { thread: String },
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Commands for managing the status file")]
 enum RecordSubcommand {
@@ -195,6 +328,7 @@ enum RecordSubcommand {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opt = GG::from_args();
+    db::set_offline(opt.offline);
     match opt.cmd {
         Cmd::New {
             feature,
@@ -203,7 +337,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         } => {
             if main {
                 git::fetch_main();
-                git::checkout_main();
+                // Uncommitted changes come along to the new branch.
+                or_exit(git::move_to(&format!(
+                    "origin/{}",
+                    config::get_saved_config().repo_main_branch
+                )));
             }
             let mut branch = git::current_parsed_branch();
             if branch.prefix.is_none() {
@@ -230,31 +368,140 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("You're trying to push a branch that has prev changes without specifying '-s', do you want to push the start branch as well?");
                 start = confirm();
             }
-            let mut branches = vec![cur.clone()];
+            let parsed = git::parse_branch(cur.clone());
+            let mut branches = vec![cur];
             if start {
-                branches.push(git::parse_branch(cur).start());
+                branches.push(parsed.start());
             }
             git::push(branches, force);
+            if parsed.partx100.is_some() {
+                let forge = forge::connect(config::get_full_config()).await;
+                forge::stack_nav::update(forge.as_ref(), &parsed.base).await;
+            }
         }
         Cmd::Pr {
             use_start,
             is_draft,
+            reviewers,
+            labels,
+            template,
+            edit,
+            discard_draft,
         } => {
             let branch = git::current_parsed_branch();
+            if discard_draft {
+                match forge::draft::clear(&branch.full()) {
+                    true => println!("Discarded the saved draft for {}", branch.full()),
+                    false => println!("There's no saved draft for {}", branch.full()),
+                }
+                return Ok(());
+            }
+            if edit {
+                let forge = forge::connect(config::get_full_config()).await;
+                if let Err(e) = forge.edit_pr(branch.full()).await {
+                    println!("{}", color::red(format!("Couldn't edit the PR: {}", e)));
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
             git::push_one(branch.full(), true);
-            let cfg = config::get_full_config();
-            let github = GithubRepo::new(cfg).await;
+            let forge = forge::connect(config::get_full_config()).await;
             let base = match use_start {
                 true => {
                     git::push_one(branch.start(), true);
                     Some(branch.start())
                 }
+                false if git::is_first_part(&branch) => git::stack_dependency(&branch.base),
                 false => None,
             };
+            forge
+                .create_pr(
+                    branch.full(),
+                    base,
+                    is_draft,
+                    PrMetadata {
+                        reviewers,
+                        labels,
+                        assignees: vec![],
+                    },
+                    template,
+                )
+                .await
+                .unwrap_or_else(|e| {
+                    println!("{}", color::red(format!("Couldn't create the PR: {}", e)));
+                    std::process::exit(1);
+                });
+            forge::stack_nav::update(forge.as_ref(), &branch.base).await;
+        }
+        Cmd::Commit { message, all } => {
+            if git::commit(message, all, false, false) {
+                gg_id::ensure_head_has_id();
+                or_exit(restack_children(None));
+            }
+        }
+        Cmd::Amend {
+            message,
+            all,
+            no_edit,
+        } => {
+            gg_id::keep_id_for_amend();
+            if git::commit(message, all, true, no_edit) {
+                gg_id::ensure_head_has_id();
+                or_exit(restack_children(None));
+            }
+        }
+        Cmd::PrUpdate {
+            ready,
+            draft,
+            auto_merge,
+            no_auto_merge,
+            reviewers,
+            title,
+            body,
+        } => {
+            let github = forge::connect_github(get_full_config()).await;
+            let branch = current_branch();
+            if ready {
+                github
+                    .mark_ready_for_review(branch.clone())
+                    .await
+                    .expect("error marking PR ready");
+            }
+            if draft {
+                github
+                    .convert_to_draft(branch.clone())
+                    .await
+                    .expect("error converting PR to draft");
+            }
+            if let Some(method) = auto_merge {
+                github
+                    .enable_auto_merge(
+                        branch.clone(),
+                        &MergeOptions {
+                            method: method.to_uppercase(),
+                            title: None,
+                            message: None,
+                        },
+                    )
+                    .await
+                    .expect("error enabling auto-merge");
+            }
+            if no_auto_merge {
+                github
+                    .disable_auto_merge(branch.clone())
+                    .await
+                    .expect("error disabling auto-merge");
+            }
             github
-                .create_pr(branch.full(), base, is_draft)
+                .request_reviewers(branch.clone(), reviewers)
                 .await
-                .expect("error creating PR");
+                .expect("error requesting reviewers");
+            if title.is_some() || body.is_some() {
+                github
+                    .update_title_and_body(branch, title, body)
+                    .await
+                    .expect("error updating PR");
+            }
         }
         Cmd::Fetch {} => {
             git::fetch_main();
@@ -265,59 +512,94 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Cmd::Log { all } => {
             log(all).await;
         }
-        Cmd::Land {} => {
-            let cfg = config::get_full_config();
-            let github = GithubRepo::new(cfg).await;
+        Cmd::Land {
+            stack,
+            abort,
+            auto,
+            force,
+            method,
+            title,
+            message,
+        } => {
+            if abort {
+                land::clear_state();
+                println!("Forgot the stack that was being landed");
+                return Ok(());
+            }
+            let opts = land::LandOptions {
+                force,
+                method,
+                title,
+                message,
+            };
+            let forge = forge::connect(config::get_full_config()).await;
+            // Scripts and CI need to see a land that didn't happen as a failure.
+            if stack {
+                if !land::land_stack(forge.as_ref(), &opts).await {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
             let branch = git::current_parsed_branch();
-            let pr = github
+            if git::is_first_part(&branch) {
+                if let Some(dep) = git::stack_dependency(&branch.base) {
+                    println!(
+                        "{} builds on {}, land that first (or drop it with 'gg depend --clear')",
+                        branch.full(),
+                        dep
+                    );
+                    std::process::exit(1);
+                }
+            }
+            let pr = forge
                 .pr_for_branch(&branch.full())
                 .await
                 .expect("error getting PR")
                 .unwrap();
-            github
-                .land_pr(branch.full())
-                .await
-                .expect("error landing PR");
-            git::fetch_main();
-            git::checkout_main();
-            git::delete_branch_all(branch.full());
-            git::delete_branch_all(branch.start());
+            if auto {
+                if !land::auto_land_branch(forge.as_ref(), &branch, &pr, &opts).await {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            if !land::land_branch(forge.as_ref(), &branch, &pr, &opts).await {
+                std::process::exit(1);
+            }
             let selected_issue = config::get_selected_issue_number();
             if selected_issue > 0 {
-                let issue = github.get_issue(selected_issue).await?;
-                print!(
-                    "Close issue '{}' github.com/{}/{}/issues/{}?\n[y/n]: ",
-                    issue.title, github.org, github.repo, selected_issue
+                let issue = forge
+                    .get_issue(selected_issue)
+                    .await
+                    .expect("error getting issue");
+                println!(
+                    "Close issue '{}' {}?",
+                    issue.title,
+                    forge.issue_url(selected_issue)
                 );
-                io::stdout().flush().unwrap();
-                let res = std::io::stdin()
-                    .bytes()
-                    .next()
-                    .and_then(|result| result.ok())
-                    .unwrap() as char;
-                if res == 'y' {
-                    github.close_issue(selected_issue).await?;
+                if confirm() {
+                    forge
+                        .close_issue(selected_issue)
+                        .await
+                        .expect("error closing issue");
                     config::update_selected_issue(0);
                 }
             }
-            let title = pr.title.unwrap_or_else(|| "Untitled PR".to_string());
-            record::write_status(format!("Landed: {}", title), false);
         }
         Cmd::RebaseOld { interactive } => {
             git::rebase(interactive);
         }
         Cmd::Issue(issue) => {
-            let cfg = config::get_full_config();
-            let github = GithubRepo::new(cfg).await;
+            let forge = forge::connect(config::get_full_config()).await;
             match issue {
                 IssueSubcommand::Create { title } => {
-                    github
+                    let issue = forge
                         .create_issue(title.as_str(), "")
                         .await
                         .expect("error creating");
+                    println!("Created Issue: {}", color::bold(color::green(issue.url)));
                 }
                 IssueSubcommand::List {} => {
-                    github.list_issues().await.expect("error creating");
+                    forge.list_issues().await.expect("error creating");
                 }
             }
         }
@@ -325,8 +607,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             terminal::start_pr_terminal().await.unwrap();
         }
         Cmd::Branch {} => {
-            let github = GithubRepo::new(get_full_config()).await;
-            let mut branches = terminal::branches::load_branch_infos(&github).await;
+            let forge = forge::connect(get_full_config()).await;
+            let mut branches = terminal::branches::load_branch_infos(forge.as_ref()).await;
             branches.sort_by_key(|b| b.date_created);
             for branch in branches {
                 println!(
@@ -345,13 +627,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     color::bold(branch.branch),
                     match branch.pr {
                         Some(pr) => format!(
-                            "{} ({}) {} {}",
-                            color::bold(color::white(pr.url)),
+                            "{} ({}) {} {}{}",
+                            color::bold(color::white(pr.url.as_str())),
                             color::bold(match pr.closed {
                                 true => color::red("Closed"),
                                 false => color::green("Open"),
                             }),
-                            match pr.review_decision {
+                            match &pr.review_decision {
                                 Some(d) => {
                                     match d.as_str() {
                                         "APPROVED" => color::green('✔'),
@@ -370,6 +652,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 "EXPECTED" => color::blue('?'),
                                 val => val.to_string(),
                             },
+                            match pr.merge_state() {
+                                Some(state) => format!(" {}", color::blue(state)),
+                                None => "".to_string(),
+                            },
                         ),
                         None => "".to_string(),
                     }
@@ -389,7 +675,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         },
         Cmd::Debug {} => {
             println!("trying change base");
-            let github = GithubRepo::new(get_full_config()).await;
+            let github = forge::connect_github(get_full_config()).await;
             github
                 .change_base(
                     "wh/pr_updatebase_part-1.0".to_string(),
@@ -398,8 +684,39 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await
                 .expect("error getting PRs");
         }
+        Cmd::Auth(cmd) => auth_command(cmd).await,
         Cmd::Init {} => {
-            config::get_full_config();
+            config::get_saved_config();
+            gg_id::install_commit_msg_hook();
+        }
+        Cmd::Depend { on, clear } => {
+            let branch = current_parsed_branch();
+            if clear {
+                config::update_stack_dependency(&branch.base, None);
+            } else if let Some(on) = on {
+                git::assert_branch_exists(on.clone());
+                if let Some(chain) = git::dependency_cycle(&branch.base, &on) {
+                    println!(
+                        "{}",
+                        color::red(format!(
+                            "{} can't build on {}, that would loop: {}",
+                            branch.base,
+                            on,
+                            chain.join(" -> ")
+                        ))
+                    );
+                    std::process::exit(1);
+                }
+                config::update_stack_dependency(&branch.base, Some(on));
+                println!("Run 'gg rebase' on the first part of the stack to move it onto its new base");
+            }
+            match get_saved_config().stack_deps.get(&branch.base) {
+                Some(dep) => println!("{} builds on {}", branch.base, color::bold(dep)),
+                None => println!("{} builds on main", branch.base),
+            }
+        }
+        Cmd::CommitMsg { file } => {
+            gg_id::add_to_message_file(file.as_str());
         }
         Cmd::Pomodoro { duration_mins } => {
             pomodoro::run_pomodoro(duration_mins);
@@ -419,10 +736,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             } else if start {
                 git::CheckoutDir::Start
             } else {
-                git::CheckoutDir::Unknown
+                if let Some(x) = terminal::start_checkout_picker().await? {
+                    or_exit(git::switch_to(&x));
+                }
+                return Ok(());
             };
             match git::get_branch_for_dir(dir) {
-                Some(x) => git::checkout(&x),
+                Some(x) => or_exit(git::switch_to(&x)),
                 None => println!("No branch found!"),
             };
         }
@@ -433,14 +753,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 None => current_parsed_branch(),
             };
             if no_branch {
-                // Checkout a different branch before deleting ourself.
-                git::checkout(&match dest {
+                // Checkout a different branch before deleting ourself, taking any uncommitted
+                // changes along since nobody's coming back for them.
+                or_exit(git::move_to(&match dest {
                     Some(dest) => dest,
                     None => match git::get_branch_for_dir(git::CheckoutDir::Prev) {
                         Some(x) => x,
                         None => format!("origin/{}", get_saved_config().repo_main_branch),
                     },
-                });
+                }));
             }
             git::delete_branch_all(branch_to_delete.full());
             git::delete_branch_all(branch_to_delete.start());
@@ -453,20 +774,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             rebase_continue,
             rebase_cleanup,
         } => {
-            if rebase_cleanup {
-                fixup_rebase();
-                return Ok(());
+            let res = if rebase_cleanup {
+                fixup_rebase().and_then(|()| git::finish_autostash())
             } else if rebase_abort {
-                abort_rebase();
-                return Ok(());
+                abort_rebase().and_then(|()| git::finish_autostash())
             } else if rebase_continue {
-                continue_rebase();
+                // The resolved conflict is in the worktree, so there's nothing to stash here,
+                // just changes to pick back up from when the rebase started.
+                continue_rebase()
+                    .and_then(|()| match all {
+                        true => rebase_all_children(strategy),
+                        false => Ok(()),
+                    })
+                    .and_then(|()| git::finish_autostash())
             } else {
-                start_rebase(onto, strategy.clone());
-            }
-            if all {
-                rebase_all_children(strategy);
-            }
+                git::with_autostash(|| {
+                    start_rebase(onto, strategy.clone())?;
+                    if all {
+                        rebase_all_children(strategy)?;
+                    }
+                    Ok(())
+                })
+            };
+            or_exit(res);
         }
         Cmd::Diff {} => {
             diff(current_parsed_branch().start(), None);
@@ -474,11 +804,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Cmd::Status {} => {
             status(current_parsed_branch().start(), None);
         }
+        Cmd::Reviews {
+            quickfix,
+            cmd: None,
+        } => {
+            let forge = forge::connect(get_full_config()).await;
+            reviews::inbox(forge.as_ref(), quickfix).await;
+        }
+        Cmd::Reviews {
+            cmd: Some(ReviewsSubcommand::Walk {}),
+            ..
+        } => {
+            terminal::start_threads_terminal()
+                .await
+                .expect("error walking review threads");
+        }
+        Cmd::Reviews { cmd: Some(cmd), .. } => {
+            let github = forge::connect_github(get_full_config()).await;
+            match cmd {
+                ReviewsSubcommand::Reply { thread, body } => github
+                    .reply_to_thread(&thread, &body)
+                    .await
+                    .expect("error replying to thread"),
+                ReviewsSubcommand::Resolve { thread } => github
+                    .resolve_thread(&thread)
+                    .await
+                    .expect("error resolving thread"),
+                ReviewsSubcommand::Unresolve { thread } => github
+                    .unresolve_thread(&thread)
+                    .await
+                    .expect("error unresolving thread"),
+                ReviewsSubcommand::Done { thread } => {
+                    let comment = github
+                        .last_comment_id(&thread)
+                        .await
+                        .expect("error getting thread");
+                    github
+                        .mark_done(&comment)
+                        .await
+                        .expect("error reacting to thread");
+                }
+                ReviewsSubcommand::Walk {} => unreachable!(),
+            }
+        }
         Cmd::Cleanup { force } => {
             cleanup(force).await;
         }
         Cmd::Sync { force } => {
-            sync(force);
+            or_exit(git::with_autostash(|| {
+                sync(force);
+                Ok(())
+            }));
         }
         Cmd::Migrate { prefix, separator } => {
             migrate(prefix.as_str(), separator.as_str());
@@ -493,9 +869,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Cmd::Setbase {} => {
             let cfg = get_full_config();
             let mainbr = cfg.saved.repo_main_branch.clone();
-            let github = GithubRepo::new(cfg).await;
+            let forge = forge::connect(cfg).await;
             let cur = current_branch();
-            github
+            forge
                 .change_base(cur, mainbr)
                 .await
                 .expect("error getting PRs");
@@ -504,6 +880,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+async fn auth_command(cmd: AuthSubcommand) {
+    let saved = get_saved_config();
+    let host = or_exit(saved.host());
+    let env_var = saved.token_env_var();
+    match cmd {
+        AuthSubcommand::Login { token } => {
+            let token = token.unwrap_or_else(|| {
+                println!("Paste a token for {}: ", host);
+                let stdin = std::io::stdin();
+                termion::input::TermRead::read_passwd(&mut stdin.lock(), &mut std::io::stdout())
+                    .expect("reading token")
+                    .unwrap_or_default()
+            });
+            let token = token.trim().to_string();
+            let info = match auth::validate_token(&saved, token.clone()).await {
+                Ok(info) => info,
+                Err(e) => {
+                    println!("{}", color::red(format!("Token didn't work: {}", e)));
+                    return;
+                }
+            };
+            let missing = info.missing_scopes();
+            if !missing.is_empty() {
+                println!(
+                    "{}",
+                    color::red(format!("Token is missing scopes: {}", missing.join(", ")))
+                );
+                return;
+            }
+            if let Err(e) = auth::save_token(&host, token) {
+                println!("{}", color::red(e.to_string()));
+                std::process::exit(1);
+            }
+            println!("Logged in to {} as {}", host, color::green(info.login));
+        }
+        AuthSubcommand::Status {} => {
+            let (token, source) = match auth::find_token(&host, env_var) {
+                Ok(Some(found)) => found,
+                Ok(None) => {
+                    println!("Not logged in to {}, run `gg auth login`", host);
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    println!("{}", color::red(e.to_string()));
+                    std::process::exit(1);
+                }
+            };
+            println!("Using token for {} from the {}", host, source.describe());
+            match auth::validate_token(&saved, token).await {
+                Ok(info) => {
+                    println!("Logged in as {}", color::green(info.login.clone()));
+                    match &info.scopes {
+                        Some(scopes) => println!("Scopes: {}", scopes.join(", ")),
+                        None => println!("Scopes: not reported (fine-grained token)"),
+                    }
+                    let missing = info.missing_scopes();
+                    if !missing.is_empty() {
+                        println!("{}", color::red(format!("Missing scopes: {}", missing.join(", "))));
+                    }
+                }
+                Err(e) => println!("{}", color::red(format!("Token didn't work: {}", e))),
+            }
+        }
+        AuthSubcommand::Logout {} => {
+            match auth::remove_token(&host) {
+                Ok(true) => println!("Removed the token for {}", host),
+                Ok(false) => println!("No saved token for {}", host),
+                Err(e) => {
+                    println!("{}", color::red(e.to_string()));
+                    std::process::exit(1);
+                }
+            }
+            if let Ok(Some((_, source))) = auth::find_token(&host, env_var) {
+                println!("A token is still available from the {}", source.describe());
+            }
+        }
+    }
+}
+
 fn migrate(prefix: &str, separator: &str) {
     let branches = git::all_parsed_managed_branches();
     for branch in branches {
@@ -529,22 +984,24 @@ fn migrate(prefix: &str, separator: &str) {
 }
 
 async fn cleanup(force: bool) {
-    let cfg = config::get_full_config();
-    let github = GithubRepo::new(cfg).await;
-    let branches = git::all_branches();
+    let forge = forge::connect(config::get_full_config()).await;
+    let branches = git::all_managed_branches();
     let mut br_map = HashSet::new();
     for branch in &branches {
         br_map.insert(branch.clone());
     }
-    let prs = github
+    let prs = forge
         .prs_for_branches(&br_map)
         .await
         .expect("error getting PRs");
 
+    // Branches already offered for their closed PR, deleted or not, aren't asked about again.
+    let mut handled = HashSet::new();
     for pr in prs {
         if !pr.closed {
             continue;
         }
+        handled.insert(pr.branch.clone());
         if !force {
             println!(
                 "Do you want to delete {}, {}, \"{}\"?",
@@ -557,13 +1014,54 @@ async fn cleanup(force: bool) {
 
         cleanup_closed_pr(&pr)
     }
+
+    // Squash merges leave no closed PR behind for branches pushed elsewhere, so also
+    // look for branches whose Gg-Ids have all made it to main.
+    git::fetch_main();
+    let main = format!("origin/{}", get_saved_config().repo_main_branch);
+    let existing: HashSet<String> = git::all_branches().into_iter().collect();
+    for branch in git::all_parsed_managed_branches() {
+        if handled.contains(&branch.full()) {
+            continue;
+        }
+        if !existing.contains(&branch.full()) || !existing.contains(&branch.start()) {
+            continue;
+        }
+        if !gg_id::is_landed(&branch, main.as_str()) {
+            continue;
+        }
+        if !force {
+            println!(
+                "Every commit on {} has landed in {}, do you want to delete it?",
+                branch.full(),
+                main
+            );
+            if !confirm() {
+                continue;
+            }
+        }
+        cleanup_branch(&branch);
+    }
 }
 
 fn cleanup_closed_pr(pr: &Pr) {
-    let br = parse_branch(pr.branch.clone());
+    cleanup_branch(&parse_branch(pr.branch.clone()));
+}
+
+fn cleanup_branch(br: &ParsedBranch) {
+    let payload = HookPayload::for_branch(br, None);
     println!("Deleting {} and {}", br.full(), br.start());
     delete_branch_all(br.full());
     delete_branch_all(br.start());
+    hooks::run(Hook::PostCleanup, payload).expect("post-cleanup hook");
+}
+
+// For git errors the user can fix and try again, there's no need for a backtrace.
+fn or_exit<T>(res: anyhow::Result<T>) -> T {
+    res.unwrap_or_else(|e| {
+        println!("{}", color::red(e.to_string()));
+        std::process::exit(1);
+    })
 }
 
 fn confirm() -> bool {
@@ -584,9 +1082,15 @@ async fn log(all: bool) {
     } else {
         branches.push(git::current_branch());
     }
-    let cfg = config::get_full_config();
-    let github = GithubRepo::new(cfg).await;
+    let forge = forge::connect(config::get_full_config()).await;
+    let prs: HashMap<String, Pr> = forge
+        .prs_for_branches(&branches.iter().cloned().collect())
+        .await
+        .expect("error seeing PRs")
+        .into_iter()
+        .map(|pr| (pr.branch.clone(), pr))
+        .collect();
     for branch in branches {
-        github.pr_status(branch).await.expect("error seeing PR");
+        forge::print_pr(prs.get(&branch), branch);
     }
 }
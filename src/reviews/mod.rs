@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{
+    color,
+    forge::{Forge, Pr, ReviewThread},
+    git::{self, ParsedBranch},
+};
+
+// A thread to show, with the PR it's on and the stack part that owns its file today.
+pub(crate) struct InboxEntry {
+    pub(crate) pr: Pr,
+    pub(crate) owner: String,
+    pub(crate) thread: ReviewThread,
+}
+
+// Unresolved review threads and general comments on the open PRs of every managed branch,
+// grouped by file and line. `quickfix` prints `file:line:col: message` lines for an editor
+// instead, general comments have no place in the code so they're left out of those.
+pub(crate) async fn inbox(forge: &dyn Forge, quickfix: bool) {
+    let (by_file, general) = unresolved(forge).await;
+    if quickfix {
+        for (path, entries) in &by_file {
+            for e in entries {
+                println!(
+                    "{}:{}:1: [{}] {}",
+                    path,
+                    e.thread.line.unwrap_or(1),
+                    e.owner,
+                    summary(&e.thread)
+                );
+            }
+        }
+        return;
+    }
+    if by_file.is_empty() && general.is_empty() {
+        println!("Nothing to go through");
+        return;
+    }
+    for (path, entries) in &by_file {
+        println!("{}", color::bold(color::blue(path)));
+        for e in entries {
+            println!(
+                "  {:>5} {} {} {}",
+                e.thread
+                    .line
+                    .map(|l| l.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                color::yellow(short_sha(e.thread.commit.as_deref())),
+                color::green(format!("[{}]", e.owner)),
+                summary(&e.thread)
+            );
+            // The thread id is what `gg reviews reply/resolve/done` take.
+            println!(
+                "        {} {}",
+                color::white(comment_url(e)),
+                e.thread.id
+            );
+        }
+    }
+    if !general.is_empty() {
+        println!("{}", color::bold(color::blue("General comments")));
+        for e in &general {
+            println!(
+                "  #{} {} {}",
+                e.pr.number,
+                color::green(format!("[{}]", e.owner)),
+                summary(&e.thread)
+            );
+            println!("        {}", color::white(comment_url(e)));
+        }
+    }
+}
+
+// The unresolved threads on file lines, by file and sorted by line, and the general comments.
+pub(crate) async fn unresolved(
+    forge: &dyn Forge,
+) -> (BTreeMap<String, Vec<InboxEntry>>, Vec<InboxEntry>) {
+    let branches: HashSet<String> = git::all_managed_branches().into_iter().collect();
+    let mut prs: Vec<Pr> = forge
+        .prs_for_branches(&branches)
+        .await
+        .expect("error getting PRs")
+        .into_iter()
+        .filter(|pr| pr.state == "OPEN")
+        .collect();
+    prs.sort_by_key(|pr| pr.number);
+
+    let mut owners = Owners::default();
+    let mut by_file: BTreeMap<String, Vec<InboxEntry>> = BTreeMap::new();
+    let mut general = vec![];
+    for pr in prs {
+        let threads = match forge.review_threads(&pr).await {
+            Ok(threads) => threads,
+            Err(e) => {
+                println!("{}", color::red(format!("{}: {}", pr.url, e)));
+                continue;
+            }
+        };
+        let branch = git::parse_branch(pr.branch.clone());
+        for thread in threads.into_iter().filter(|t| !t.resolved) {
+            let entry = |owner: String| InboxEntry {
+                pr: pr.clone(),
+                owner,
+                thread: thread.clone(),
+            };
+            match &thread.path {
+                Some(path) => by_file
+                    .entry(path.clone())
+                    .or_default()
+                    .push(entry(owners.owner_of(&branch, path))),
+                None => general.push(entry(pr.branch.clone())),
+            }
+        }
+    }
+    for entries in by_file.values_mut() {
+        entries.sort_by_key(|e| e.thread.line);
+    }
+
+    (by_file, general)
+}
+
+fn summary(thread: &ReviewThread) -> String {
+    let first = match thread.comments.first() {
+        Some(c) => c,
+        None => return String::new(),
+    };
+    let text = first.body.lines().next().unwrap_or("").trim();
+    let replies = match thread.comments.len() - 1 {
+        0 => String::new(),
+        1 => " (+1 reply)".to_string(),
+        n => format!(" (+{} replies)", n),
+    };
+    format!("{}: {}{}", first.author, text, replies)
+}
+
+fn comment_url(e: &InboxEntry) -> String {
+    e.thread
+        .comments
+        .first()
+        .map(|c| c.url.clone())
+        .unwrap_or_else(|| e.pr.url.clone())
+}
+
+fn short_sha(sha: Option<&str>) -> String {
+    match sha {
+        Some(sha) => sha.chars().take(7).collect(),
+        None => "-------".to_string(),
+    }
+}
+
+// Which part of a stack owns a file: the highest part that changes it, since that's where a
+// fix would go today. Files no part changes stay with the PR's own part.
+#[derive(Default)]
+struct Owners {
+    changed: HashMap<String, Vec<String>>,
+}
+
+impl Owners {
+    fn owner_of(&mut self, branch: &ParsedBranch, path: &str) -> String {
+        for part in git::get_sorted_matching_branches(&branch.base).iter().rev() {
+            let changed = self
+                .changed
+                .entry(part.full())
+                .or_insert_with(|| git::changed_files(&part.start(), &part.full()));
+            if changed.iter().any(|f| f == path) {
+                return part.full();
+            }
+        }
+        branch.full()
+    }
+}
@@ -14,7 +14,7 @@ use tui::{
 
 use crate::{
     git::{self, is_start_branch, parse_branch},
-    github::{pr::Pr, GithubRepo},
+    forge::{Forge, Pr},
 };
 
 use super::{app::App, InputResult};
@@ -27,15 +27,16 @@ pub(crate) struct BranchWithInfo {
     pub(crate) pr: Option<Pr>,
 }
 
-static WIDTHS: &[tui::layout::Constraint; 4] = &[
+static WIDTHS: &[tui::layout::Constraint; 5] = &[
     Constraint::Length(1),
     Constraint::Length(1),
     Constraint::Length(50),
     Constraint::Length(50),
+    Constraint::Length(12),
 ];
 
 impl BranchWithInfo {
-    fn to_row(&self, selected: bool) -> Row {
+    fn to_row(&self, selected: bool) -> Row<'_> {
         let mut style = Style::default();
         if selected {
             style = Style::default().bg(Color::LightGreen);
@@ -60,6 +61,10 @@ impl BranchWithInfo {
                 }
                 None => Cell::from("N/A").style(style.fg(Color::Red)),
             },
+            match self.pr.as_ref().and_then(|p| p.merge_state()) {
+                Some(state) => Cell::from(state).style(style.fg(Color::Yellow)),
+                None => Cell::from(""),
+            },
         ])
         .style(style)
     }
@@ -70,16 +75,18 @@ impl BranchWithInfo {
 }
 
 // TODO Move
-pub(crate) async fn load_branch_infos(github: &GithubRepo) -> Vec<BranchWithInfo> {
+pub(crate) async fn load_branch_infos(forge: &dyn Forge) -> Vec<BranchWithInfo> {
     let branches = git::all_branch_infos();
     let mut br_map = HashMap::new();
     let mut br_set = HashSet::new();
     let current_branch = git::current_branch();
     for branch in &branches {
         br_map.insert(branch.name.clone(), branch);
-        br_set.insert(branch.name.clone());
+        if !is_start_branch(&branch.name) {
+            br_set.insert(branch.name.clone());
+        }
     }
-    let prs = github.prs_for_branches(&br_set).await.unwrap();
+    let prs = forge.prs_for_branches(&br_set).await.unwrap();
     let mut pr_map = HashMap::new();
     for pr in prs {
         pr_map.insert(pr.branch.clone(), pr);
@@ -109,22 +116,22 @@ pub(super) struct PullApp {
     // TODO Not only pulls, include branches
     pulls: Vec<BranchWithInfo>,
     selection: usize,
-    github: GithubRepo,
+    forge: Box<dyn Forge>,
 }
 
 impl PullApp {
-    pub(super) async fn new(github: GithubRepo) -> PullApp {
+    pub(super) async fn new(forge: Box<dyn Forge>) -> PullApp {
         let mut p = PullApp {
             pulls: vec![],
             selection: 0,
-            github,
+            forge,
         };
         p.load_branch_infos().await;
         p
     }
 
     async fn load_branch_infos(&mut self) {
-        self.pulls = load_branch_infos(&self.github).await;
+        self.pulls = load_branch_infos(self.forge.as_ref()).await;
     }
 
     fn down(&mut self) {
@@ -198,16 +205,13 @@ impl App for PullApp {
             }
             Key::Char('c') => {
                 let selected_branch = &self.pulls[self.selection];
-                git::checkout(&selected_branch.branch);
+                git::switch_to(&selected_branch.branch).map_err(|e| e.to_string())?;
                 self.update().await;
             }
             Key::Char('\n') => {
                 let i = &self.pulls[self.selection];
-                match &i.pr {
-                    Some(pr) => {
-                        open::that(&pr.url.as_str()).unwrap();
-                    }
-                    None => {}
+                if let Some(pr) = &i.pr {
+                    open::that(pr.url.as_str()).unwrap();
                 }
             }
             _ => {
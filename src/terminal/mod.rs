@@ -1,8 +1,9 @@
 mod app;
 pub mod branches;
+mod picker;
+mod threads;
 
 use async_trait::async_trait;
-use octocrab::models::issues::Issue;
 use std::{error::Error, io, sync::mpsc, thread, time::Duration};
 use termion::input::TermRead;
 use termion::{event::Key, raw::IntoRawMode, screen::AlternateScreen};
@@ -19,10 +20,10 @@ use tui::{
 
 use crate::{
     config::{self, get_full_config},
-    github::GithubRepo,
+    forge::{self, Forge, Issue},
 };
 
-use self::{app::App, branches::PullApp};
+use self::{app::App, branches::PullApp, picker::PickerApp, threads::ThreadsApp};
 
 pub enum Event<I> {
     Input(I),
@@ -46,7 +47,7 @@ impl Events {
             let tx = tx.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys().into_iter().flatten() {
+                for evt in stdin.keys().flatten() {
                     if let Err(err) = tx.send(Event::Input(evt)) {
                         eprintln!("{}", err);
                         return;
@@ -93,23 +94,23 @@ enum InputResult {
 }
 
 struct IssueViewApp {
-    issues: Vec<octocrab::models::issues::Issue>,
+    issues: Vec<Issue>,
     selection: usize,
     input_state: InputState,
     buffered_issue_title: String,
     selected_issue: u64,
-    github: GithubRepo,
+    forge: Box<dyn Forge>,
 }
 
 impl IssueViewApp {
-    async fn new(github: GithubRepo) -> IssueViewApp {
+    async fn new(forge: Box<dyn Forge>) -> IssueViewApp {
         IssueViewApp {
-            issues: github.get_issues().await.unwrap(),
+            issues: forge.get_issues().await.unwrap(),
             selection: 0,
             input_state: InputState::Normal,
             buffered_issue_title: String::new(),
             selected_issue: 0,
-            github,
+            forge,
         }
     }
 
@@ -140,7 +141,7 @@ impl IssueViewApp {
 #[async_trait]
 impl App for IssueViewApp {
     async fn update(&mut self) {
-        let issues = self.github.get_issues().await.unwrap();
+        let issues = self.forge.get_issues().await.unwrap();
         self.issues = issues;
         if self.selection >= self.issues.len() {
             self.selection = self.issues.len() - 1
@@ -169,7 +170,7 @@ impl App for IssueViewApp {
                 }
                 ListItem::new(Spans::from(vec![
                     Span::styled(prefix, style.fg(Color::Red)),
-                    Span::styled(i.html_url.as_str(), style.fg(Color::Blue)),
+                    Span::styled(i.url.as_str(), style.fg(Color::Blue)),
                     Span::styled(" ", style),
                     Span::styled(i.title.as_str(), style),
                 ]))
@@ -210,7 +211,10 @@ impl App for IssueViewApp {
                 }
                 Key::Char('d') => {
                     let i = self.get_selected();
-                    self.github.close_issue(i.number).await?;
+                    self.forge
+                        .close_issue(i.number)
+                        .await
+                        .map_err(|e| e.to_string())?;
                     self.update().await;
                 }
                 Key::Char('c') => {
@@ -218,7 +222,7 @@ impl App for IssueViewApp {
                 }
                 Key::Char('\n') => {
                     let i = self.get_selected();
-                    open::that(&i.html_url.as_str()).unwrap();
+                    open::that(i.url.as_str()).unwrap();
                 }
                 _ => {
                     println!("Unknown input!");
@@ -232,9 +236,10 @@ impl App for IssueViewApp {
                     self.buffered_issue_title.pop();
                 }
                 Key::Char('\n') => {
-                    self.github
+                    self.forge
                         .create_issue(self.buffered_issue_title.clone().as_str(), "")
-                        .await?;
+                        .await
+                        .map_err(|e| e.to_string())?;
                     self.set_input_state(InputState::Normal);
                     self.update().await;
                 }
@@ -257,20 +262,35 @@ pub async fn start_pr_terminal() -> Result<(), Box<dyn Error>> {
 }
 
 async fn start_terminal_with_opts(view: View) -> Result<(), Box<dyn Error>> {
-    let github = GithubRepo::new(get_full_config()).await;
+    let forge = forge::connect(get_full_config()).await;
     match view {
         View::Issues => {
-            run_loop(IssueViewApp::new(github).await).await?;
+            run_loop(&mut IssueViewApp::new(forge).await).await?;
         }
         View::Pulls => {
-            run_loop(PullApp::new(github).await).await?;
+            run_loop(&mut PullApp::new(forge).await).await?;
         }
     };
 
     Ok(())
 }
 
-async fn run_loop<T: App>(mut app: T) -> Result<(), Box<dyn Error>> {
+// Returns the branch picked, or None if the picker was closed without picking.
+pub async fn start_checkout_picker() -> Result<Option<String>, Box<dyn Error>> {
+    let forge = forge::connect(get_full_config()).await;
+    let mut app = PickerApp::new(forge.as_ref()).await;
+    run_loop(&mut app).await?;
+    Ok(app.picked)
+}
+
+// Review threads are resolved through github's graphql api, so this one is github only.
+pub async fn start_threads_terminal() -> Result<(), Box<dyn Error>> {
+    let github = forge::connect_github(get_full_config()).await;
+    run_loop(&mut ThreadsApp::new(github).await).await?;
+    Ok(())
+}
+
+async fn run_loop<T: App>(app: &mut T) -> Result<(), Box<dyn Error>> {
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
@@ -0,0 +1,225 @@
+use async_trait::async_trait;
+use std::error::Error;
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    Frame,
+};
+
+use crate::{forge::Forge, git::parse_branch};
+
+use super::{
+    app::App,
+    branches::{load_branch_infos, BranchWithInfo},
+    InputResult,
+};
+
+static WIDTHS: &[tui::layout::Constraint; 4] = &[
+    Constraint::Length(1),
+    Constraint::Length(10),
+    Constraint::Length(50),
+    Constraint::Length(60),
+];
+
+// Type-ahead picker over every managed branch, used by `gg checkout` with no args.
+pub(super) struct PickerApp {
+    branches: Vec<BranchWithInfo>,
+    query: String,
+    matches: Vec<usize>,
+    selection: usize,
+    pub(super) picked: Option<String>,
+}
+
+impl PickerApp {
+    pub(super) async fn new(forge: &dyn Forge) -> PickerApp {
+        let mut branches: Vec<BranchWithInfo> = load_branch_infos(forge)
+            .await
+            .into_iter()
+            .filter(|b| parse_branch(b.branch.clone()).prefix.is_some())
+            .collect();
+        branches.sort_by_key(|b| -b.date_created);
+        let mut p = PickerApp {
+            branches,
+            query: String::new(),
+            matches: vec![],
+            selection: 0,
+            picked: None,
+        };
+        p.refilter();
+        p
+    }
+
+    fn refilter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .branches
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, b)| {
+                let title = b.pr.as_ref().map(|p| p.title.as_str()).unwrap_or("");
+                fuzzy_score(&self.query, &format!("{} {}", b.branch, title)).map(|s| (s, idx))
+            })
+            .collect();
+        // Stable sort keeps the most recently committed branches first among equal scores.
+        scored.sort_by_key(|(score, _)| -score);
+        self.matches = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.selection = 0;
+    }
+
+    fn down(&mut self) {
+        if self.selection + 1 < self.matches.len() {
+            self.selection += 1;
+        }
+    }
+
+    fn up(&mut self) {
+        if self.selection > 0 {
+            self.selection -= 1;
+        }
+    }
+
+    fn to_row(&self, branch: &BranchWithInfo, selected: bool) -> Row<'_> {
+        let mut style = Style::default();
+        if selected {
+            style = Style::default().bg(Color::LightGreen);
+        }
+        let date = chrono::NaiveDateTime::from_timestamp_opt(branch.date_created, 0)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        Row::new(vec![
+            match branch.current {
+                true => Cell::from("*").style(style.fg(Color::Red)),
+                false => Cell::from(" "),
+            },
+            Cell::from(date).style(style.fg(Color::Yellow)),
+            Cell::from(branch.branch.clone()).style(style.fg(Color::Blue)),
+            match &branch.pr {
+                Some(pr) => Cell::from(pr.title.clone()).style(style),
+                None => Cell::from("N/A").style(style.fg(Color::Red)),
+            },
+        ])
+        .style(style)
+    }
+}
+
+// Scores `candidate` if every character of `query` appears in it in order (case
+// insensitive). Consecutive and early matches score higher.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match: Option<usize> = None;
+    for q in query.to_lowercase().chars() {
+        let found = candidate[pos..].iter().position(|c| *c == q)? + pos;
+        score += match last_match {
+            Some(last) if last + 1 == found => 10,
+            _ => 1,
+        };
+        if found == 0 {
+            score += 5;
+        }
+        last_match = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+#[async_trait]
+impl App for PickerApp {
+    async fn update(&mut self) {}
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        let input = Paragraph::new(Text::from(Spans::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Red)),
+            Span::raw(self.query.as_str()),
+        ])))
+        .block(Block::default().borders(Borders::ALL).title("Checkout"));
+        f.render_widget(input, chunks[0]);
+
+        let items: Vec<Row> = self
+            .matches
+            .iter()
+            .enumerate()
+            .map(|(idx, b)| self.to_row(&self.branches[*b], idx == self.selection))
+            .collect();
+        let title = format!("{}/{} Branches", self.matches.len(), self.branches.len());
+        let table = Table::new(items)
+            .widths(WIDTHS)
+            .column_spacing(1)
+            .block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(table, chunks[1]);
+    }
+
+    async fn handle_input(&mut self, input: Key) -> Result<InputResult, Box<dyn Error>> {
+        match input {
+            Key::Esc | Key::Ctrl('c') => {
+                return Ok(InputResult::Exit);
+            }
+            Key::Char('\n') => {
+                if let Some(idx) = self.matches.get(self.selection) {
+                    self.picked = Some(self.branches[*idx].branch.clone());
+                    return Ok(InputResult::Exit);
+                }
+            }
+            Key::Down | Key::Ctrl('n') | Key::Ctrl('j') => {
+                self.down();
+            }
+            Key::Up | Key::Ctrl('p') | Key::Ctrl('k') => {
+                self.up();
+            }
+            Key::Backspace => {
+                self.query.pop();
+                self.refilter();
+            }
+            Key::Char(c) => {
+                self.query.push(c);
+                self.refilter();
+            }
+            _ => {}
+        }
+        Ok(InputResult::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_score;
+
+    #[test]
+    fn needs_every_char_in_order() {
+        assert!(fuzzy_score("abc", "a-b-c").is_some());
+        assert!(fuzzy_score("cba", "a-b-c").is_none());
+        assert!(fuzzy_score("abcd", "a-b-c").is_none());
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(
+            fuzzy_score("FIX", "fix-login"),
+            fuzzy_score("fix", "FIX-LOGIN")
+        );
+    }
+
+    #[test]
+    fn consecutive_and_early_matches_score_higher() {
+        let consecutive = fuzzy_score("log", "login").unwrap();
+        let scattered = fuzzy_score("log", "l-o-g").unwrap();
+        assert!(consecutive > scattered);
+        let early = fuzzy_score("f", "fix").unwrap();
+        let late = fuzzy_score("f", "elf").unwrap();
+        assert!(early > late);
+    }
+
+    #[test]
+    fn an_empty_query_matches_anything() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+}
@@ -0,0 +1,314 @@
+use async_trait::async_trait;
+use std::error::Error;
+use termion::event::Key;
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::{
+    forge::ReviewComment,
+    github::GithubRepo,
+    reviews::{self, InboxEntry},
+};
+
+use super::{app::App, InputResult};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InputState {
+    Normal,
+    Reply,
+}
+
+// Walks the unresolved review threads on your open PRs one at a time, by file and line, with
+// the diff hunk each was left on. Threads stay in the list once resolved so it can be undone.
+pub(super) struct ThreadsApp {
+    entries: Vec<InboxEntry>,
+    selection: usize,
+    input_state: InputState,
+    reply: String,
+    // The result of the last action, shown under the thread.
+    message: String,
+    failed: bool,
+    github: GithubRepo,
+}
+
+impl ThreadsApp {
+    pub(super) async fn new(github: GithubRepo) -> ThreadsApp {
+        let (by_file, _) = reviews::unresolved(&github).await;
+        ThreadsApp {
+            entries: by_file.into_values().flatten().collect(),
+            selection: 0,
+            input_state: InputState::Normal,
+            reply: String::new(),
+            message: String::new(),
+            failed: false,
+            github,
+        }
+    }
+
+    fn next(&mut self) {
+        if self.selection + 1 < self.entries.len() {
+            self.selection += 1;
+            self.message.clear();
+        }
+    }
+
+    fn prev(&mut self) {
+        if self.selection > 0 {
+            self.selection -= 1;
+            self.message.clear();
+        }
+    }
+
+    fn selected(&mut self) -> &mut InboxEntry {
+        &mut self.entries[self.selection]
+    }
+
+    fn header(e: &InboxEntry) -> Spans<'_> {
+        let path = e.thread.path.as_deref().unwrap_or("");
+        let line = e.thread.line.map(|l| format!(":{}", l)).unwrap_or_default();
+        let state = match e.thread.resolved {
+            true => Span::styled(" resolved", Style::default().fg(Color::Green)),
+            false => Span::styled(" unresolved", Style::default().fg(Color::Red)),
+        };
+        Spans::from(vec![
+            Span::styled(
+                format!("{}{}", path, line),
+                Style::default()
+                    .fg(Color::Blue)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(format!(" [{}]", e.owner), Style::default().fg(Color::Green)),
+            Span::raw(format!(" #{} {}", e.pr.number, e.pr.title)),
+            state,
+        ])
+    }
+
+    fn hunk(e: &InboxEntry) -> Text<'_> {
+        let hunk = match &e.thread.diff_hunk {
+            Some(hunk) => hunk,
+            None => return Text::raw("No diff hunk"),
+        };
+        Text::from(
+            hunk.lines()
+                .map(|l| {
+                    let color = match l.chars().next() {
+                        Some('+') => Color::Green,
+                        Some('-') => Color::Red,
+                        Some('@') => Color::Cyan,
+                        _ => Color::Reset,
+                    };
+                    Spans::from(Span::styled(l, Style::default().fg(color)))
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn comments(e: &InboxEntry) -> Text<'_> {
+        let mut lines = vec![];
+        for c in &e.thread.comments {
+            lines.push(Spans::from(Span::styled(
+                c.author.as_str(),
+                Style::default().fg(Color::Yellow),
+            )));
+            lines.extend(c.body.lines().map(|l| Spans::from(format!("  {}", l))));
+            lines.push(Spans::from(""));
+        }
+        Text::from(lines)
+    }
+
+    // Failures stay in the walk as the message, with the reply kept to try again.
+    fn show_result(&mut self, res: anyhow::Result<String>) -> bool {
+        match res {
+            Ok(message) => {
+                self.message = message;
+                self.failed = false;
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                self.failed = true;
+            }
+        }
+        !self.failed
+    }
+
+    async fn send_reply(&mut self) -> anyhow::Result<String> {
+        let body = self.reply.trim().to_string();
+        if body.is_empty() {
+            return Ok(String::new());
+        }
+        let thread = self.entries[self.selection].thread.id.clone();
+        self.github.reply_to_thread(&thread, &body).await?;
+        // Shown until the next time the threads are loaded.
+        let author = self.github.current_user.clone();
+        self.selected().thread.comments.push(ReviewComment {
+            id: String::new(),
+            author,
+            body,
+            url: String::new(),
+        });
+        Ok("Replied".to_string())
+    }
+
+    async fn set_resolved(&mut self, resolved: bool) -> anyhow::Result<String> {
+        let thread = self.entries[self.selection].thread.id.clone();
+        match resolved {
+            true => self.github.resolve_thread(&thread).await?,
+            false => self.github.unresolve_thread(&thread).await?,
+        };
+        self.selected().thread.resolved = resolved;
+        Ok(match resolved {
+            true => "Resolved".to_string(),
+            false => "Unresolved".to_string(),
+        })
+    }
+
+    // Reacts to the last comment someone else left, your own replies don't need a thumbs up.
+    async fn mark_done(&mut self) -> anyhow::Result<String> {
+        let me = self.github.current_user.clone();
+        let comment = self.entries[self.selection]
+            .thread
+            .comments
+            .iter()
+            .rev()
+            .find(|c| c.author != me && !c.id.is_empty())
+            .map(|c| c.id.clone())
+            .ok_or_else(|| anyhow::anyhow!("No comment from a reviewer to react to"))?;
+        self.github.mark_done(&comment).await?;
+        Ok("Marked done".to_string())
+    }
+}
+
+#[async_trait]
+impl App for ThreadsApp {
+    // Reloading would lose the place in the walk, the threads are only loaded at the start.
+    async fn update(&mut self) {}
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints(
+                [
+                    Constraint::Length(3),
+                    Constraint::Percentage(50),
+                    Constraint::Min(5),
+                    Constraint::Length(3),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+        let e = match self.entries.get(self.selection) {
+            Some(e) => e,
+            None => {
+                f.render_widget(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title("No unresolved threads"),
+                    chunks[0],
+                );
+                return;
+            }
+        };
+        let title = format!("Thread {}/{}", self.selection + 1, self.entries.len());
+        f.render_widget(
+            Paragraph::new(Self::header(e))
+                .block(Block::default().borders(Borders::ALL).title(title)),
+            chunks[0],
+        );
+        f.render_widget(
+            Paragraph::new(Self::hunk(e))
+                .block(Block::default().borders(Borders::ALL).title("Diff")),
+            chunks[1],
+        );
+        f.render_widget(
+            Paragraph::new(Self::comments(e))
+                .wrap(Wrap { trim: false })
+                .block(Block::default().borders(Borders::ALL).title("Comments")),
+            chunks[2],
+        );
+        let bottom = match self.input_state {
+            InputState::Reply => Paragraph::new(self.reply.as_str())
+                .block(Block::default().borders(Borders::ALL).title("Reply")),
+            InputState::Normal => Paragraph::new(Spans::from(vec![
+                Span::styled(
+                    self.message.as_str(),
+                    Style::default().fg(match self.failed {
+                        true => Color::Red,
+                        false => Color::Green,
+                    }),
+                ),
+                Span::raw(
+                    "  n/p: next/prev  r: reply  s: resolve  u: unresolve  d: done  o: open  q: quit",
+                ),
+            ]))
+            .block(Block::default().borders(Borders::ALL)),
+        };
+        f.render_widget(bottom, chunks[3]);
+    }
+
+    async fn handle_input(&mut self, input: Key) -> Result<InputResult, Box<dyn Error>> {
+        match self.input_state {
+            InputState::Normal => match input {
+                Key::Esc | Key::Ctrl('c') | Key::Char('q') => {
+                    return Ok(InputResult::Exit);
+                }
+                _ if self.entries.is_empty() => {}
+                Key::Char('n') | Key::Char('j') | Key::Right | Key::Down => {
+                    self.next();
+                }
+                Key::Char('p') | Key::Char('k') | Key::Left | Key::Up => {
+                    self.prev();
+                }
+                // A reply that failed to send is still there to retry.
+                Key::Char('r') => {
+                    self.input_state = InputState::Reply;
+                }
+                Key::Char('s') => {
+                    let res = self.set_resolved(true).await;
+                    self.show_result(res);
+                }
+                Key::Char('u') => {
+                    let res = self.set_resolved(false).await;
+                    self.show_result(res);
+                }
+                Key::Char('d') => {
+                    let res = self.mark_done().await;
+                    self.show_result(res);
+                }
+                Key::Char('o') => {
+                    let e = &self.entries[self.selection];
+                    let url = e.thread.comments.first().map(|c| c.url.as_str());
+                    open::that(url.unwrap_or(e.pr.url.as_str())).unwrap();
+                }
+                _ => {}
+            },
+            InputState::Reply => match input {
+                Key::Esc | Key::Ctrl('c') => {
+                    self.reply.clear();
+                    self.input_state = InputState::Normal;
+                }
+                Key::Backspace => {
+                    self.reply.pop();
+                }
+                Key::Char('\n') => {
+                    let res = self.send_reply().await;
+                    if self.show_result(res) {
+                        self.reply.clear();
+                    }
+                    self.input_state = InputState::Normal;
+                }
+                Key::Char(c) => {
+                    self.reply.push(c);
+                }
+                _ => {}
+            },
+        }
+        Ok(InputResult::Continue)
+    }
+}